@@ -0,0 +1,136 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::rng::XorShift;
+use crate::board::Board;
+use crate::cursor::Coord;
+use crate::rules::Position;
+
+/// One of the 8 ways to rotate and mirror the board onto itself.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Symmetry(u8);
+
+impl Symmetry {
+    fn all() -> impl Iterator<Item = Self> {
+        (0..8).map(Symmetry)
+    }
+    /// Mirrors if the third bit is set, then turns a quarter as many times
+    /// as the lower two bits say.
+    fn apply(self, pos: &Coord) -> Coord {
+        let (mut x, mut y) = (pos.x, pos.y);
+        if self.0 & 4 != 0 {
+            x = -x;
+        }
+        for _ in 0..self.0 & 3 {
+            let turned = (-y, x);
+            x = turned.0;
+            y = turned.1;
+        }
+        Coord::new_bounded(x, y)
+    }
+    fn invert(self, pos: &Coord) -> Coord {
+        let (mut x, mut y) = (pos.x, pos.y);
+        for _ in 0..self.0 & 3 {
+            let turned = (y, -x);
+            x = turned.0;
+            y = turned.1;
+        }
+        if self.0 & 4 != 0 {
+            x = -x;
+        }
+        Coord::new_bounded(x, y)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BookMove {
+    pub pos: Coord,
+    /// How often the move is picked relative to the others.
+    pub weight: u32,
+}
+
+/// Moves to play in known positions, without searching. Positions are stored
+/// in a canonical orientation, so an entry also matches every rotation and
+/// mirror image of its position.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Book {
+    pub positions: BTreeMap<String, Vec<BookMove>>,
+}
+
+impl Book {
+    /// Picks one of the book moves for the position at random by weight.
+    pub fn probe(&self, position: &Position) -> Option<Coord> {
+        let (key, symmetry) = canonical(position);
+        let moves = self.positions.get(&key)?;
+        let total: u32 = moves.iter().map(|book_move| book_move.weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = XorShift::from_time().below(total as usize) as u32;
+        let book_move = moves.iter().find(|book_move| {
+            if pick < book_move.weight {
+                return true;
+            }
+            pick -= book_move.weight;
+            false
+        })?;
+        let pos = symmetry.invert(&book_move.pos);
+        if position.check(&pos).is_err() {
+            info!("book move {} is illegal here", pos);
+            return None;
+        }
+        Some(pos)
+    }
+
+    /// Counts `pos` as played in `position` once more.
+    pub fn add(&mut self, position: &Position, pos: &Coord) {
+        let (key, symmetry) = canonical(position);
+        let pos = symmetry.apply(pos);
+        let moves = self.positions.entry(key).or_default();
+        match moves.iter_mut().find(|book_move| book_move.pos == pos) {
+            Some(book_move) => book_move.weight += 1,
+            None => moves.push(BookMove { pos, weight: 1 }),
+        }
+    }
+}
+
+/// The key of the position, the same for all its symmetric images, and the
+/// symmetry that turns the position into the one the key describes.
+fn canonical(position: &Position) -> (String, Symmetry) {
+    let board = &position.board;
+    let (stones, symmetry) = Symmetry::all()
+        .map(|symmetry| (stones(board, symmetry), symmetry))
+        .min_by(|(a, _), (b, _)| a.cmp(b))
+        .expect("no symmetries");
+    let mut key = format!(
+        "{}{}",
+        side_char(position.side().idx()),
+        if position.bonus { "+" } else { "" }
+    );
+    for (idx, piece) in stones {
+        key.push_str(&format!(" {}{}", side_char(piece), idx));
+    }
+    (key, symmetry)
+}
+
+/// The stones on the board as seen through `symmetry`, sorted.
+fn stones(board: &Board, symmetry: Symmetry) -> Vec<(usize, usize)> {
+    let mut stones: Vec<_> = board
+        .pieces()
+        .map(|(idx, piece)| {
+            let pos = symmetry.apply(&board.idx2pos(idx));
+            (board.pos2idx(&pos), piece.idx())
+        })
+        .collect();
+    stones.sort();
+    stones
+}
+
+fn side_char(piece: usize) -> char {
+    if piece == 0 {
+        'b'
+    } else {
+        'w'
+    }
+}
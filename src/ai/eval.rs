@@ -0,0 +1,95 @@
+use std::collections::HashSet;
+
+use crate::board::Piece;
+use crate::cursor::Coord;
+use crate::pattern::{find_threats, ThreatKind};
+use crate::rules::Position;
+
+pub const FIVE_SCORE: i32 = 10_000;
+const BONUS_TURN_SCORE: i32 = 500;
+/// Within this many cells of a stone, empty cells are worth considering.
+const NEIGHBORHOOD: i32 = 2;
+
+fn threat_score(kind: ThreatKind, to_move: bool) -> i32 {
+    match (kind, to_move) {
+        // Completed on the next move.
+        (ThreatKind::Four, true) | (ThreatKind::OpenFour, true) => FIVE_SCORE * 4 / 5,
+        (ThreatKind::OpenFour, false) => FIVE_SCORE / 2,
+        (ThreatKind::Four, false) => 600,
+        (ThreatKind::OpenThree, _) => 250,
+        (ThreatKind::BrokenThree, _) => 150,
+    }
+}
+
+/// Scores the position for the side to move.
+pub fn evaluate(position: &Position) -> i32 {
+    let side = position.side();
+    let fives = position.fives[side.idx()] as i32 - position.fives[side.next().idx()] as i32;
+    let mut score = FIVE_SCORE * fives;
+    if position.bonus {
+        score += BONUS_TURN_SCORE;
+    }
+    for threat in find_threats(&position.board) {
+        if threat.piece == side {
+            score += threat_score(threat.kind, true);
+        } else {
+            score -= threat_score(threat.kind, false);
+        }
+    }
+    score
+}
+
+/// A quick guess of how good a move is, used to order and prune moves.
+fn move_score(position: &Position, pos: &Coord, side: Piece) -> i32 {
+    let board = &position.board;
+    if board.five_if_placed(pos, side).is_some() {
+        return FIVE_SCORE * 100;
+    }
+    let opponent = side.next();
+    // Capturing breaks the opponent's rows through the cell as well.
+    let blocks_five = board.five_if_placed(pos, opponent).is_some();
+    let ours: i32 = board.run_lengths(pos, side).iter().map(|n| n * n * n).sum();
+    let theirs: i32 = board
+        .run_lengths(pos, opponent)
+        .iter()
+        .map(|n| n * n * n)
+        .sum();
+    let mut score = ours + theirs * 4 / 5;
+    if blocks_five {
+        score += FIVE_SCORE * 50;
+    }
+    if board.get_piece(pos).is_some() {
+        score += theirs;
+    }
+    score
+}
+
+/// The most promising legal moves, best first: empty cells near the stones
+/// on board, and in a bonus turn the opponent stones as well.
+pub fn candidate_moves(position: &Position, breadth: usize) -> Vec<Coord> {
+    let board = &position.board;
+    let side = position.side();
+    let mut near = HashSet::new();
+    for (idx, _) in board.pieces() {
+        let stone = board.idx2pos(idx);
+        for dy in -NEIGHBORHOOD..=NEIGHBORHOOD {
+            for dx in -NEIGHBORHOOD..=NEIGHBORHOOD {
+                if !board.out_of_bound(stone.x + dx, stone.y + dy) {
+                    near.insert(board.pos2idx(&Coord::new_bounded(stone.x + dx, stone.y + dy)));
+                }
+            }
+        }
+    }
+    let mut moves: Vec<_> = position
+        .legal_moves()
+        .into_iter()
+        .filter(|pos| near.contains(&board.pos2idx(pos)))
+        .map(|pos| (move_score(position, &pos, side), pos))
+        .collect();
+    if moves.is_empty() && position.check(&Coord::new_bounded(0, 0)).is_ok() {
+        return vec![Coord::new_bounded(0, 0)];
+    }
+    moves.sort_by(|(a, _), (b, _)| b.cmp(a));
+    moves.truncate(breadth);
+    moves.into_iter().map(|(_, pos)| pos).collect()
+}
@@ -0,0 +1,213 @@
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::eval::{candidate_moves, evaluate, FIVE_SCORE};
+use super::rng::XorShift;
+use super::{AiConfig, SearchResult};
+use crate::board::Piece;
+use crate::cursor::Coord;
+use crate::rules::Position;
+
+/// How much the search explores rarely visited moves rather than the best.
+const EXPLORATION: f64 = 1.4;
+/// Playouts pick among this many of the most promising moves.
+const PLAYOUT_BREADTH: usize = 3;
+/// How many playouts are run between two progress reports.
+const REPORT_INTERVAL: u32 = 256;
+/// The evaluation at which a playout counts as three quarters of a win.
+const EVAL_SCALE: f64 = FIVE_SCORE as f64 / 2.0;
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+struct Node {
+    /// The move leading here, and the side that played it.
+    pos: Option<Coord>,
+    piece: Piece,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Coord>,
+    visits: u32,
+    /// Summed playout results for `piece`, from 0 for a loss to 1 for a win.
+    wins: f64,
+}
+
+impl Node {
+    fn uct(&self, parent_visits: u32) -> f64 {
+        let visits = self.visits as f64;
+        self.wins / visits + EXPLORATION * ((parent_visits as f64).ln() / visits).sqrt()
+    }
+}
+
+/// Monte Carlo tree search over the endless rules: rather than trusting the
+/// evaluation a few moves ahead, it plays many short games from the position
+/// and keeps playing the moves that turn out well. Searches on one thread.
+pub struct Mcts {
+    config: AiConfig,
+    stop: Arc<AtomicBool>,
+    rng: XorShift,
+    nodes: Vec<Node>,
+}
+
+impl Mcts {
+    /// A search that gives up as soon as `stop` is set.
+    pub fn with_stop(config: AiConfig, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            config,
+            stop,
+            rng: XorShift::new(SEED),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Runs the configured number of playouts, or fewer when stopped or out
+    /// of time, calling `report` every now and then.
+    pub fn search<F>(&mut self, position: Position, mut report: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        let deadline = self.config.time_limit().map(|limit| Instant::now() + limit);
+        self.nodes = vec![Node {
+            pos: None,
+            piece: position.side().next(),
+            parent: None,
+            children: Vec::new(),
+            untried: candidate_moves(&position, self.config.breadth),
+            visits: 0,
+            wins: 0.0,
+        }];
+        let mut playouts = 0;
+        while playouts < self.config.playouts.max(1) {
+            if self.stop.load(Ordering::Relaxed)
+                || deadline.map_or(false, |deadline| Instant::now() >= deadline)
+            {
+                info!("search stopped after {} playouts", playouts);
+                break;
+            }
+            self.iterate(position.clone());
+            playouts += 1;
+            if playouts % REPORT_INTERVAL == 0 {
+                report(&self.result(&position));
+            }
+        }
+        let result = self.result(&position);
+        info!(
+            "{} playouts score {} best {:?}",
+            playouts, result.score, result.best
+        );
+        result
+    }
+
+    /// One round of selection, expansion, playout and backpropagation.
+    fn iterate(&mut self, mut position: Position) {
+        let mut node = 0;
+        while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+            let parent_visits = self.nodes[node].visits;
+            node = *self.nodes[node]
+                .children
+                .iter()
+                .max_by(|a, b| {
+                    let a = self.nodes[**a].uct(parent_visits);
+                    let b = self.nodes[**b].uct(parent_visits);
+                    a.partial_cmp(&b).expect("uct is not a number")
+                })
+                .expect("node has no children");
+            let pos = self.nodes[node].pos.expect("child without a move");
+            position.play(&pos).expect("tree move is illegal");
+        }
+
+        if !self.nodes[node].untried.is_empty() {
+            let pick = self.rng.below(self.nodes[node].untried.len());
+            let pos = self.nodes[node].untried.swap_remove(pick);
+            let piece = position.side();
+            if position.play(&pos).is_ok() {
+                let child = self.nodes.len();
+                self.nodes.push(Node {
+                    pos: Some(pos),
+                    piece,
+                    parent: Some(node),
+                    children: Vec::new(),
+                    untried: candidate_moves(&position, self.config.breadth),
+                    visits: 0,
+                    wins: 0.0,
+                });
+                self.nodes[node].children.push(child);
+                node = child;
+            }
+        }
+
+        let (winner, value) = self.playout(position);
+        let mut current = Some(node);
+        while let Some(idx) = current {
+            let node = &mut self.nodes[idx];
+            node.visits += 1;
+            node.wins += if node.piece == winner {
+                value
+            } else {
+                1.0 - value
+            };
+            current = node.parent;
+        }
+    }
+
+    /// Plays on with quick heuristic moves, then scores the final position
+    /// as how likely its side to move is to be ahead.
+    fn playout(&mut self, mut position: Position) -> (Piece, f64) {
+        for _ in 0..self.config.playout_depth {
+            if position.is_over() {
+                break;
+            }
+            let moves = candidate_moves(&position, PLAYOUT_BREADTH);
+            if moves.is_empty() {
+                break;
+            }
+            // The best move is worth more than the rest together.
+            let pick = if self.rng.below(2) == 0 {
+                0
+            } else {
+                self.rng.below(moves.len())
+            };
+            if position.play(&moves[pick]).is_err() {
+                break;
+            }
+        }
+        let score = evaluate(&position) as f64;
+        (
+            position.side(),
+            0.5 + 0.5 * score / (score.abs() + EVAL_SCALE),
+        )
+    }
+
+    fn result(&self, position: &Position) -> SearchResult {
+        let mut pv = Vec::new();
+        let mut node = 0;
+        while let Some(child) = self.nodes[node]
+            .children
+            .iter()
+            .max_by_key(|child| self.nodes[**child].visits)
+        {
+            node = *child;
+            pv.push(self.nodes[node].pos.expect("child without a move"));
+        }
+        let (best, score) = match self.nodes[0]
+            .children
+            .iter()
+            .max_by_key(|child| self.nodes[**child].visits)
+        {
+            Some(child) => {
+                let child = &self.nodes[*child];
+                let rate = child.wins / child.visits as f64;
+                (child.pos, ((rate - 0.5) * 2.0 * FIVE_SCORE as f64) as i32)
+            }
+            // Not even one playout: fall back on the most promising move.
+            None => (candidate_moves(position, 1).first().cloned(), 0),
+        };
+        SearchResult {
+            best,
+            score,
+            depth: pv.len() as u32,
+            nodes: self.nodes.len() as u64,
+            pv,
+        }
+    }
+}
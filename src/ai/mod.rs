@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::rules::Position;
+
+mod book;
+mod eval;
+mod mcts;
+mod rng;
+mod search;
+mod tt;
+mod worker;
+
+pub use book::Book;
+pub use eval::candidate_moves;
+pub use mcts::Mcts;
+pub use rng::XorShift;
+pub use search::{Engine, SearchResult};
+pub use worker::{SearchHandle, SharedEngine};
+
+/// How the engine chooses its moves.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Personality {
+    /// Looks a fixed number of moves ahead with alpha-beta search.
+    AlphaBeta,
+    /// Plays many short random games with Monte Carlo tree search.
+    Mcts,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AiConfig {
+    pub personality: Personality,
+    /// How many moves ahead to search, counting bonus turns as moves.
+    pub depth: u32,
+    /// How many of the most promising moves are searched at each node.
+    pub breadth: usize,
+    /// How many games `Personality::Mcts` plays out per move.
+    pub playouts: u32,
+    /// How many moves a playout goes on before the position is evaluated.
+    pub playout_depth: u32,
+    /// How many seconds a search may take before it settles for the deepest
+    /// line finished so far. `None` searches to the full depth.
+    pub time_limit: Option<f32>,
+    /// How many threads search at once. 0 uses every core.
+    pub threads: usize,
+    /// The opening book in the config directory, played from before searching.
+    pub book: Option<String>,
+    /// Whether the engine plays the `Turn::Computer` side.
+    pub opponent: bool,
+    /// Whether the engine lets the player take moves back.
+    pub grants_takebacks: bool,
+    /// The engine accepts a draw when its last search scored its own side
+    /// at most this.
+    pub draw_score: i32,
+}
+
+impl AiConfig {
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.time_limit.map(Duration::from_secs_f32)
+    }
+    pub fn threads(&self) -> usize {
+        match self.threads {
+            0 => num_cpus::get(),
+            threads => threads,
+        }
+    }
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            personality: Personality::AlphaBeta,
+            depth: 3,
+            breadth: 12,
+            playouts: 1000,
+            playout_depth: 16,
+            time_limit: None,
+            threads: 1,
+            book: None,
+            opponent: false,
+            grants_takebacks: true,
+            draw_score: 0,
+        }
+    }
+}
+
+/// Searches with the engine of the configured personality, on the calling
+/// thread, until done or until `stop` is set. An alpha-beta search is run by
+/// `engine`, which keeps its table for the next one.
+pub fn run_search<F>(
+    engine: &mut Engine,
+    position: Position,
+    config: AiConfig,
+    stop: Arc<AtomicBool>,
+    report: F,
+) -> SearchResult
+where
+    F: FnMut(&SearchResult),
+{
+    match config.personality {
+        Personality::AlphaBeta => {
+            engine.configure(config, stop);
+            engine.search(position, report)
+        }
+        Personality::Mcts => Mcts::with_stop(config, stop).search(position, report),
+    }
+}
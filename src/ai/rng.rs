@@ -0,0 +1,27 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small, fast generator; playouts need speed, not quality.
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // All zeros would stay zero forever.
+        Self(seed | 1)
+    }
+    /// A generator that differs from run to run.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        Self::new(nanos as u64 ^ 0x2545_f491_4f6c_dd1d)
+    }
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
@@ -0,0 +1,320 @@
+use log::{error, info};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use super::eval::{candidate_moves, evaluate};
+use super::tt::{Bound, TranspositionTable, TtEntry};
+use super::AiConfig;
+use crate::cursor::Coord;
+use crate::rules::Position;
+
+const INFINITY: i32 = i32::MAX / 2;
+/// How many nodes are searched between two looks at the clock.
+const CLOCK_INTERVAL: u64 = 64;
+/// Bonus per remaining depth for completing a five-in-a-row.
+const SOONER_FIVE: i32 = 300;
+/// Slots in the transposition table, 16 bytes each.
+const TT_CAPACITY: usize = 1 << 18;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub best: Option<Coord>,
+    /// For the side to move.
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    /// The expected line of play, starting with `best`.
+    pub pv: Vec<Coord>,
+}
+
+/// Alpha-beta search over the endless rules. A bonus turn is searched as
+/// another move of the same side.
+///
+/// With more than one thread, helper threads search the same position at
+/// staggered depths and share what they find through the transposition table
+/// (Lazy SMP). Only the main thread's result is used, so with a single thread
+/// and no time limit the search is deterministic.
+///
+/// The table is kept from one search to the next, and cleared in between, so
+/// an engine that searches again and again allocates it only once.
+pub struct Engine {
+    config: AiConfig,
+    nodes: u64,
+    stop: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    aborted: bool,
+    tt: Arc<TranspositionTable>,
+    /// 0 for the main thread.
+    thread: usize,
+}
+
+impl Engine {
+    pub fn new(config: AiConfig) -> Self {
+        Self::with_stop(config, Arc::new(AtomicBool::new(false)))
+    }
+    /// An engine that gives up as soon as `stop` is set.
+    pub fn with_stop(config: AiConfig, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            config,
+            nodes: 0,
+            stop,
+            deadline: None,
+            aborted: false,
+            tt: Arc::new(TranspositionTable::new(0, 0)),
+            thread: 0,
+        }
+    }
+    /// Sets what the next search is run with, keeping the table.
+    pub fn configure(&mut self, config: AiConfig, stop: Arc<AtomicBool>) {
+        self.config = config;
+        self.stop = stop;
+    }
+
+    /// Searches with iterative deepening up to the configured depth, calling
+    /// `report` each time a depth is finished. When stopped or out of time,
+    /// the unfinished depth is thrown away and the result of the last
+    /// finished one is returned.
+    pub fn search<F>(&mut self, position: Position, report: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        self.deadline = self.config.time_limit().map(|limit| Instant::now() + limit);
+        self.nodes = 0;
+        if self.tt.cells() == position.board.size() {
+            self.tt.clear();
+        } else {
+            self.tt = Arc::new(TranspositionTable::new(position.board.size(), TT_CAPACITY));
+        }
+        let threads = self.config.threads();
+        // Helpers stop once the main thread is done, for whatever reason.
+        let done = Arc::new(AtomicBool::new(false));
+        let helpers: Vec<_> = (1..threads)
+            .map(|thread| {
+                let mut helper = Self {
+                    config: self.config.clone(),
+                    nodes: 0,
+                    stop: done.clone(),
+                    deadline: self.deadline,
+                    aborted: false,
+                    tt: self.tt.clone(),
+                    thread,
+                };
+                let position = position.clone();
+                thread::spawn(move || helper.iterate(position, |_| {}).nodes)
+            })
+            .collect();
+        let mut result = self.iterate(position, report);
+        done.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            result.nodes += helper.join().unwrap_or_else(|_| {
+                error!("search helper thread panicked");
+                0
+            });
+        }
+        if threads > 1 {
+            info!("{} nodes searched by {} threads", result.nodes, threads);
+        }
+        result
+    }
+
+    fn iterate<F>(&mut self, mut position: Position, mut report: F) -> SearchResult
+    where
+        F: FnMut(&SearchResult),
+    {
+        self.aborted = false;
+        // Something to play even if not a single depth gets finished.
+        let fallback = candidate_moves(&position, 1);
+        let mut result = SearchResult {
+            best: fallback.first().cloned(),
+            pv: fallback,
+            ..Default::default()
+        };
+        // Every other helper starts a depth ahead, so the threads spread out.
+        let first = 1 + self.thread as u32 % 2;
+        for depth in first..=self.config.depth.max(first) {
+            let (score, pv) = self.negamax(&mut position, depth, 0, -INFINITY, INFINITY);
+            if self.aborted {
+                info!("search stopped during depth {}", depth);
+                break;
+            }
+            result = SearchResult {
+                best: pv.first().cloned(),
+                score,
+                depth,
+                nodes: self.nodes,
+                pv,
+            };
+            if self.thread == 0 {
+                info!(
+                    "depth {} score {} nodes {} best {:?}",
+                    depth, score, self.nodes, result.best
+                );
+                report(&result);
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.aborted {
+            let out_of_time = self.nodes % CLOCK_INTERVAL == 0
+                && self
+                    .deadline
+                    .map_or(false, |deadline| Instant::now() >= deadline);
+            self.aborted = out_of_time || self.stop.load(Ordering::Relaxed);
+        }
+        self.aborted
+    }
+
+    fn negamax(
+        &mut self,
+        position: &mut Position,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> (i32, Vec<Coord>) {
+        self.nodes += 1;
+        if self.should_stop() {
+            return (0, Vec::new());
+        }
+        if depth == 0 || position.is_over() {
+            return (evaluate(position), Vec::new());
+        }
+
+        let key = self.tt.hash(position);
+        let hashed = self
+            .tt
+            .probe(key)
+            .filter(|entry| match entry.best {
+                Some(idx) => idx < position.board.size(),
+                None => true,
+            })
+            .map(|entry| (entry, entry.best.map(|idx| position.board.idx2pos(idx))));
+        if let Some((entry, best)) = hashed {
+            // The root always searches, so that there is a line to report.
+            let cut = ply > 0
+                && entry.depth >= depth
+                && match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score >= beta,
+                    Bound::Upper => entry.score <= alpha,
+                };
+            if cut {
+                return (entry.score, best.into_iter().collect());
+            }
+        }
+
+        let mut moves = candidate_moves(position, self.config.breadth);
+        if moves.is_empty() {
+            return (evaluate(position), Vec::new());
+        }
+        // Try the best move of an earlier search first.
+        if let Some(best) = hashed.and_then(|(_, best)| best) {
+            if let Some(idx) = moves.iter().position(|pos| *pos == best) {
+                let best = moves.remove(idx);
+                moves.insert(0, best);
+            }
+        }
+
+        let side = position.side();
+        let original_alpha = alpha;
+        let mut best = (-INFINITY, Vec::new());
+        for pos in moves {
+            let played = match position.play(&pos) {
+                Ok(played) => played,
+                Err(_) => continue,
+            };
+            let (mut score, line) = if position.side() == side {
+                self.negamax(position, depth - 1, ply + 1, alpha, beta)
+            } else {
+                let (score, line) = self.negamax(position, depth - 1, ply + 1, -beta, -alpha);
+                (-score, line)
+            };
+            position.undo(&played);
+            if self.aborted {
+                break;
+            }
+            // The same five is worth more now than later, when the opponent
+            // may have found a way to stop it.
+            if played.five.is_some() {
+                score += SOONER_FIVE * depth as i32;
+            }
+            if score > best.0 {
+                let mut pv = vec![pos];
+                pv.extend(line);
+                best = (score, pv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if !self.aborted {
+            let bound = if best.0 <= original_alpha {
+                Bound::Upper
+            } else if best.0 >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            let entry = TtEntry {
+                depth,
+                score: best.0,
+                bound,
+                best: best.1.first().map(|pos| position.board.pos2idx(pos)),
+            };
+            self.tt.store(key, entry);
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+    use crate::Turn;
+
+    fn position() -> Position {
+        let mut board = Board::new(BOARD_HALF_WIDTH);
+        let stones = [
+            (0, 0, Piece::Black),
+            (1, 0, Piece::White),
+            (1, 1, Piece::Black),
+            (2, 2, Piece::White),
+            (0, 1, Piece::Black),
+        ];
+        for (x, y, piece) in stones.iter() {
+            board.put_piece(&Coord::new_bounded(*x, *y), *piece);
+        }
+        Position::new(board, Turn::Computer, false)
+    }
+
+    #[test]
+    fn single_thread_search_is_deterministic() {
+        let config = AiConfig {
+            threads: 1,
+            time_limit: None,
+            depth: 3,
+            ..AiConfig::default()
+        };
+        let mut engine = Engine::new(config.clone());
+        let first = engine.search(position(), |_| {});
+        // Again on the same engine, whose table is cleared in between, and
+        // on a new one.
+        let again = engine.search(position(), |_| {});
+        let fresh = Engine::new(config).search(position(), |_| {});
+        for result in [again, fresh].iter() {
+            assert_eq!(result.best, first.best);
+            assert_eq!(result.score, first.score);
+            assert_eq!(result.depth, first.depth);
+            assert_eq!(result.nodes, first.nodes);
+        }
+        assert_eq!(first.depth, 3);
+    }
+}
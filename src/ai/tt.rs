@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::rules::Position;
+
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bound {
+    Exact,
+    /// The score is at least this much.
+    Lower,
+    /// The score is at most this much.
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TtEntry {
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    /// The board index of the best move found.
+    pub best: Option<usize>,
+}
+
+impl TtEntry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        let best = self.best.map_or(0, |idx| idx as u64 + 1);
+        (self.score as u32 as u64)
+            | (self.depth.min(0xff) as u64) << 32
+            | bound << 40
+            | (best & 0xffff) << 42
+    }
+    fn unpack(data: u64) -> Option<Self> {
+        let bound = match (data >> 40) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        let best = (data >> 42) & 0xffff;
+        Some(Self {
+            depth: ((data >> 32) & 0xff) as u32,
+            score: data as u32 as i32,
+            bound,
+            best: if best == 0 {
+                None
+            } else {
+                Some(best as usize - 1)
+            },
+        })
+    }
+}
+
+/// Scores of positions already searched, shared by all the search threads
+/// without locking. Each slot keeps its key xor-ed with its data, so a slot
+/// torn by two threads writing at once just reads as a miss.
+pub struct TranspositionTable {
+    slots: Vec<(AtomicU64, AtomicU64)>,
+    /// Random keys for each cell and piece, hashed together into a position key.
+    cells: Vec<[u64; 2]>,
+    side: u64,
+    bonus: u64,
+}
+
+impl TranspositionTable {
+    /// A table for a board of `cells` cells, with at least `capacity` slots.
+    pub fn new(cells: usize, capacity: usize) -> Self {
+        let mut state = SEED;
+        let mut random = || splitmix64(&mut state);
+        Self {
+            slots: (0..capacity.next_power_of_two())
+                .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+                .collect(),
+            cells: (0..cells).map(|_| [random(), random()]).collect(),
+            side: random(),
+            bonus: random(),
+        }
+    }
+
+    /// The number of cells of the board the table is for.
+    pub fn cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Forgets every position, for a new search.
+    pub fn clear(&self) {
+        for (stored, data) in self.slots.iter() {
+            stored.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn hash(&self, position: &Position) -> u64 {
+        let mut key = 0;
+        for (idx, piece) in position.board.pieces() {
+            key ^= self.cells[idx][piece.idx()];
+        }
+        if position.side().idx() == 1 {
+            key ^= self.side;
+        }
+        if position.bonus {
+            key ^= self.bonus;
+        }
+        // The score depends on the five-in-a-rows taken so far as well.
+        let mut fives = (position.fives[0] as u64) << 32 | position.fives[1] as u64;
+        key ^ splitmix64(&mut fives)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let (stored, data) = &self.slots[key as usize & (self.slots.len() - 1)];
+        let data = data.load(Ordering::Relaxed);
+        if stored.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        TtEntry::unpack(data)
+    }
+
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let (stored, data) = &self.slots[key as usize & (self.slots.len() - 1)];
+        let packed = entry.pack();
+        stored.store(key ^ packed, Ordering::Relaxed);
+        data.store(packed, Ordering::Relaxed);
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
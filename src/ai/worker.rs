@@ -0,0 +1,110 @@
+use crossbeam_channel::{unbounded, Receiver, TryRecvError};
+use log::{error, info};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use super::{run_search, AiConfig, Book, Engine, SearchResult};
+use crate::rules::Position;
+
+enum Message {
+    /// The result of a finished depth, while deeper ones are searched.
+    Progress(SearchResult),
+    Done(SearchResult),
+}
+
+/// The engine searching on worker threads, kept so that its table is
+/// allocated once. A search waits for the one before it to let go of it.
+#[derive(Clone)]
+pub struct SharedEngine(Arc<Mutex<Engine>>);
+
+impl Default for SharedEngine {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Engine::new(AiConfig::default()))))
+    }
+}
+
+/// A search running on its own thread, so the frame loop never waits for it.
+/// Dropping the handle cancels the search.
+pub struct SearchHandle {
+    receiver: Receiver<Message>,
+    stop: Arc<AtomicBool>,
+    progress: Option<SearchResult>,
+}
+
+impl SearchHandle {
+    /// Starts searching, or answers right away from the book.
+    pub fn spawn(position: Position, config: AiConfig, book: &Book, engine: &SharedEngine) -> Self {
+        let (sender, receiver) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        if let Some(pos) = book.probe(&position) {
+            info!("book move {}", pos);
+            let result = SearchResult {
+                best: Some(pos),
+                pv: vec![pos],
+                ..Default::default()
+            };
+            let _ = sender.send(Message::Done(result));
+            return Self {
+                receiver,
+                stop,
+                progress: None,
+            };
+        }
+        let stopped = stop.clone();
+        let engine = engine.0.clone();
+        thread::spawn(move || {
+            // The receiver is gone if the result is no longer wanted.
+            let report = |result: &SearchResult| {
+                let _ = sender.send(Message::Progress(result.clone()));
+            };
+            // A search that panicked leaves nothing the next one needs.
+            let mut engine = engine.lock().unwrap_or_else(PoisonError::into_inner);
+            let result = run_search(&mut engine, position, config, stopped, report);
+            let _ = sender.send(Message::Done(result));
+        });
+        Self {
+            receiver,
+            stop,
+            progress: None,
+        }
+    }
+
+    /// Returns the result once the search is done, and keeps the latest
+    /// progress report meanwhile.
+    pub fn poll(&mut self) -> Option<SearchResult> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(Message::Progress(result)) => self.progress = Some(result),
+                Ok(Message::Done(result)) => {
+                    // Nothing left to cancel.
+                    self.stop.store(true, Ordering::Relaxed);
+                    return Some(result);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    error!("search thread stopped without a result");
+                    return Some(SearchResult::default());
+                }
+            }
+        }
+    }
+
+    /// The deepest finished part of the search so far.
+    pub fn progress(&self) -> Option<&SearchResult> {
+        self.progress.as_ref()
+    }
+
+    /// Asks the search to stop. It still reports what it has found so far.
+    pub fn cancel(&self) {
+        if !self.stop.swap(true, Ordering::Relaxed) {
+            info!("search cancelled");
+        }
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
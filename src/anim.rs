@@ -0,0 +1,158 @@
+use amethyst::core::math::Vector3;
+use amethyst::core::{Time, Transform};
+use amethyst::ecs::*;
+use amethyst::renderer::resources::Tint;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicOut,
+    SineInOut,
+    BackOut,
+}
+
+impl Easing {
+    /// Maps a progress `t` in `0.0..=1.0` onto the curve.
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            }
+            Easing::CubicOut => (t - 1.0).powi(3) + 1.0,
+            Easing::SineInOut => (1.0 - (PI * t).cos()) / 2.0,
+            Easing::BackOut => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct Tween {
+    /// In seconds.
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl Tween {
+    pub fn new(duration: f32, easing: Easing) -> Self {
+        Self { duration, easing }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnimationConfig {
+    /// Scale pop of a newly placed piece.
+    pub place: Tween,
+    /// One period of the five-in-a-row blink.
+    pub blink: Tween,
+    /// Fade-out of a piece leaving the board.
+    pub remove: Tween,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            place: Tween::new(0.25, Easing::BackOut),
+            blink: Tween::new(0.6, Easing::SineInOut),
+            remove: Tween::new(0.4, Easing::QuadIn),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Effect {
+    /// Grows from half size to full size.
+    Pop,
+    /// Fades out and in again, repeating until replaced or removed.
+    Pulse,
+    /// Fades out while growing a little.
+    FadeOut,
+}
+
+/// An effect played on an entity with a `Tint` and a `Transform`. The
+/// component is removed once a non-repeating effect has finished.
+#[derive(Component)]
+pub struct Animation {
+    pub effect: Effect,
+    pub tween: Tween,
+    pub timer: f32,
+}
+
+impl Animation {
+    pub fn new(effect: Effect, tween: Tween) -> Self {
+        Self {
+            effect,
+            tween,
+            timer: 0.0,
+        }
+    }
+    fn progress(&self) -> f32 {
+        if self.tween.duration <= 0.0 {
+            return 1.0;
+        }
+        match self.effect {
+            Effect::Pulse => {
+                let phase = self.timer % self.tween.duration / self.tween.duration;
+                1.0 - (phase * 2.0 - 1.0).abs()
+            }
+            _ => (self.timer / self.tween.duration).min(1.0),
+        }
+    }
+    fn finished(&self) -> bool {
+        self.effect != Effect::Pulse && self.timer >= self.tween.duration
+    }
+}
+
+pub struct AnimationSystem;
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, Time>,
+        ReadExpect<'a, Transform>,
+        WriteStorage<'a, Animation>,
+        WriteStorage<'a, Tint>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, time, default_trans, mut animations, mut tints, mut transforms) = data;
+        let base_scale = default_trans.scale().x;
+        let mut finished = Vec::new();
+
+        let joined = (&entities, &mut animations, &mut tints, &mut transforms).join();
+        for (entity, animation, tint, transform) in joined {
+            animation.timer += time.delta_seconds();
+            let value = animation.tween.easing.apply(animation.progress());
+            let (alpha, scale) = match animation.effect {
+                Effect::Pop => (1.0, 0.5 + 0.5 * value),
+                Effect::Pulse => (1.0 - 0.8 * value, 1.0),
+                Effect::FadeOut => (1.0 - value, 1.0 + 0.3 * value),
+            };
+            tint.0.alpha = alpha;
+            transform.set_scale(Vector3::from_element(base_scale * scale));
+            if animation.finished() {
+                finished.push(entity);
+            }
+        }
+        for entity in finished {
+            animations.remove(entity);
+        }
+    }
+}
@@ -1,19 +1,25 @@
 use amethyst::core::{Hidden, Time};
 use amethyst::ecs::*;
+use amethyst::shrev::EventChannel;
 use amethyst::{GameData, SimpleState, SimpleTrans, StateData, Trans};
 use log::info;
 use std::time::Duration;
 
+use crate::anim::{Animation, AnimationConfig, Effect};
+use crate::board::Board;
+use crate::cursor::Coord;
+use crate::event::GameEvent;
+use crate::sync::PieceEntities;
 use crate::{BonusTurn, Turn};
 
 pub struct PiecesBlinkState {
-    pub fir: [Entity; 5],
+    pub fir: [Coord; 5],
     pub time: Duration,
     pub turn: Turn,
 }
 
 impl PiecesBlinkState {
-    pub fn new(fir: [Entity; 5], time: Duration, turn: Turn) -> Self {
+    pub fn new(fir: [Coord; 5], time: Duration, turn: Turn) -> Self {
         Self { fir, time, turn }
     }
 }
@@ -22,15 +28,19 @@ impl SimpleState for PiecesBlinkState {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         info!("in PiecesBlinkState ");
         let world = data.world;
-        let mut blink_storage = world.write_storage::<Blink>();
-        for entity in self.fir.iter() {
-            let blink = Blink {
-                delay: 0.6,
-                timer: 0.0,
-            };
-            blink_storage
-                .insert(*entity, blink)
-                .expect("unable to insert blink");
+        world
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .single_write(GameEvent::FiveInARow(self.turn.piece(), self.fir));
+        let board = world.fetch::<Board>();
+        let rendered = world.fetch::<PieceEntities>();
+        let tween = world.fetch::<AnimationConfig>().blink;
+        let mut animations = world.write_storage::<Animation>();
+        for pos in self.fir.iter() {
+            if let Some(entity) = rendered.get(board.pos2idx(pos)) {
+                animations
+                    .insert(entity, Animation::new(Effect::Pulse, tween))
+                    .expect("unable to insert animation");
+            }
         }
     }
 
@@ -38,11 +48,14 @@ impl SimpleState for PiecesBlinkState {
         let world = data.world;
         world.fetch_mut::<BonusTurn>().0 = true;
         *world.fetch_mut::<Turn>() = self.turn;
-        let entities = world.entities_mut();
-        self.fir
-            .iter()
-            .inspect(|entity| info!("entity {:?} removed", entity))
-            .for_each(|entity| entities.delete(*entity).expect("unable to delete entity"));
+        let mut board = world.fetch_mut::<Board>();
+        for pos in self.fir.iter() {
+            info!("piece at {} removed", pos);
+            board.remove_piece(pos);
+        }
+        world
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .single_write(GameEvent::FiveRemoved(self.turn.piece()));
     }
 
     fn fixed_update(&mut self, data: StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
@@ -57,12 +70,6 @@ impl SimpleState for PiecesBlinkState {
     }
 }
 
-#[derive(Component)]
-pub struct Blink {
-    pub delay: f32,
-    pub timer: f32,
-}
-
 pub trait ToggleHidden {
     fn toggle_hidden(hiddens: &mut WriteStorage<Hidden>, show: bool, entity: Entity) {
         match (show, hiddens.contains(entity)) {
@@ -74,30 +81,3 @@ pub trait ToggleHidden {
         };
     }
 }
-
-pub struct BlinkSystem;
-
-impl ToggleHidden for BlinkSystem {}
-
-impl<'a> System<'a> for BlinkSystem {
-    type SystemData = (
-        Entities<'a>,
-        WriteStorage<'a, Hidden>,
-        WriteStorage<'a, Blink>,
-        Read<'a, Time>,
-    );
-
-    fn run(&mut self, (entities, mut hiddens, mut blinks, time): Self::SystemData) {
-        let abs_sec = time.delta_seconds();
-
-        for (entity, blink) in (&*entities, &mut blinks).join() {
-            blink.timer += abs_sec;
-
-            if blink.timer > blink.delay {
-                blink.timer -= blink.delay;
-            }
-
-            Self::toggle_hidden(&mut hiddens, blink.timer < blink.delay / 2.0, entity);
-        }
-    }
-}
@@ -4,16 +4,20 @@ use amethyst::core::Transform;
 use amethyst::ecs::*;
 use amethyst::renderer::sprite::SpriteSheetHandle;
 use amethyst::renderer::SpriteRender;
+use amethyst::shrev::EventChannel;
 use log::info;
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
 use crate::cursor::Coord;
+use crate::event::GameEvent;
 use crate::{Turn, ARENA_HEIGHT, ARENA_WIDTH, GRID_OFFSET};
 
 pub const BOARD_HALF_WIDTH: i32 = 7; // -7..=7
+pub const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
 
-#[derive(Debug, Copy, Clone, PartialEq, Component)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Component, Deserialize, Serialize)]
 pub enum Piece {
     Black,
     White,
@@ -40,12 +44,14 @@ impl Piece {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Board {
     half_width: i32,
     width: i32,
     pieces: HashMap<usize, Piece>,
-    entity_map: HashMap<usize, Entity>,
+    move_count: u32,
+    move_numbers: HashMap<usize, u32>,
+    last_move: Option<usize>,
     five_in_a_row: Option<[usize; 5]>,
 }
 
@@ -58,6 +64,16 @@ impl Board {
             ..Default::default()
         }
     }
+    pub fn half_width(&self) -> i32 {
+        self.half_width
+    }
+    /// The number of cells on the board.
+    pub fn size(&self) -> usize {
+        (self.width * self.width) as usize
+    }
+    pub fn is_full(&self) -> bool {
+        self.pieces.len() as i32 == self.width * self.width
+    }
     pub fn out_of_bound(&self, x: i32, y: i32) -> bool {
         x < -self.half_width || x > self.half_width || y < -self.half_width || y > self.half_width
     }
@@ -83,53 +99,126 @@ impl Board {
     pub fn get_piece(&self, pos: &Coord) -> Option<&Piece> {
         self.pieces.get(&self.pos2idx(pos))
     }
+    pub fn get_piece_by_idx(&self, idx: usize) -> Option<&Piece> {
+        self.pieces.get(&idx)
+    }
+    pub fn pieces(&self) -> impl Iterator<Item = (usize, Piece)> + '_ {
+        self.pieces.iter().map(|(idx, piece)| (*idx, *piece))
+    }
     pub fn set_piece(&mut self, pos: &Coord, piece: Piece) -> bool {
-        if let Entry::Vacant(entry) = self.pieces.entry(self.pos2idx(pos)) {
+        let idx = self.pos2idx(pos);
+        if let Entry::Vacant(entry) = self.pieces.entry(idx) {
             info!("{:?} piece inserted at {}", piece, pos);
             entry.insert(piece);
+            self.move_count += 1;
+            self.move_numbers.insert(idx, self.move_count);
+            self.last_move = Some(idx);
+            self.five_in_a_row = self.calc_five_in_a_row(pos);
             return true;
         }
         false
     }
+    /// Puts `piece` at `pos` and returns the five-in-a-row it completes,
+    /// without the bookkeeping `set_piece` does for the displayed game.
+    pub fn place(&mut self, pos: &Coord, piece: Piece) -> Option<[Coord; 5]> {
+        self.put_piece(pos, piece);
+        self.five_if_placed(pos, piece)
+    }
+    /// Puts `piece` at `pos`, replacing whatever is there, without looking
+    /// for a five-in-a-row.
+    pub fn put_piece(&mut self, pos: &Coord, piece: Piece) {
+        self.pieces.insert(self.pos2idx(pos), piece);
+    }
     pub fn remove_piece(&mut self, pos: &Coord) -> Option<Piece> {
         let idx = self.pos2idx(pos);
+        self.move_numbers.remove(&idx);
         self.pieces.remove(&idx)
     }
 
-    pub fn put_entity(&mut self, pos: &Coord, entity: Entity) {
-        self.entity_map.insert(self.pos2idx(pos), entity);
-        self.five_in_a_row = self.calc_five_in_a_row(pos);
+    /// The number of the move that placed the piece at `idx`, counting from 1.
+    pub fn move_number(&self, idx: usize) -> Option<u32> {
+        self.move_numbers.get(&idx).cloned()
     }
-    pub fn get_entity(&self, pos: &Coord) -> Option<Entity> {
-        self.entity_map.get(&self.pos2idx(pos)).cloned()
+    /// The most recently placed piece, as long as it is still on the board.
+    pub fn last_move(&self) -> Option<Coord> {
+        self.last_move
+            .filter(|idx| self.pieces.contains_key(idx))
+            .map(|idx| self.idx2pos(idx))
     }
-    pub fn remove_entity(&mut self, pos: &Coord) -> Option<Entity> {
-        let idx = self.pos2idx(pos);
-        self.entity_map.remove(&idx)
+
+    pub fn has_five_in_a_row(&self) -> bool {
+        self.five_in_a_row.is_some()
     }
 
-    pub fn take_five_in_a_row(&mut self) -> Option<([Entity; 5], Turn)> {
+    /// Takes the pending five-in-a-row, if any. The pieces stay on the board
+    /// until the caller removes them, so they can still be rendered meanwhile.
+    pub fn take_five_in_a_row(&mut self) -> Option<([Coord; 5], Turn)> {
         if let Some(line) = self.five_in_a_row.take() {
             info!("take away five-in-a-row");
             let turn = self.pieces[&line[0]].turn();
-            for idx in line.iter() {
-                self.pieces.remove(idx);
-            }
-            let entities = [
-                self.entity_map.remove(&line[0]).unwrap(),
-                self.entity_map.remove(&line[1]).unwrap(),
-                self.entity_map.remove(&line[2]).unwrap(),
-                self.entity_map.remove(&line[3]).unwrap(),
-                self.entity_map.remove(&line[4]).unwrap(),
-            ];
-            return Some((entities, turn));
+            return Some((self.line2pos(&line), turn));
         }
         None
     }
+    /// The five-in-a-row that placing `piece` at `pos` would complete,
+    /// regardless of what is at `pos` now.
+    pub fn five_if_placed(&self, pos: &Coord, piece: Piece) -> Option<[Coord; 5]> {
+        self.find_five_in_a_row(pos, piece, None)
+            .map(|line| self.line2pos(&line))
+    }
+    /// The length of the row of `piece` through `pos` in each direction, as if
+    /// `piece` were placed there.
+    pub fn run_lengths(&self, pos: &Coord, piece: Piece) -> [i32; 4] {
+        let mut lengths = [0; 4];
+        for (length, (dx, dy)) in lengths.iter_mut().zip(DIRECTIONS.iter()) {
+            *length = self.count_ours(pos, piece, None, -*dx, -*dy)
+                + self.count_ours(pos, piece, None, *dx, *dy)
+                + 1;
+        }
+        lengths
+    }
+    /// The cells where the opponent could complete a five-in-a-row on the
+    /// next turn, once `piece` is placed at `pos`, or captures the stone on
+    /// it. None if the move completes a five-in-a-row itself, since it earns
+    /// a bonus turn and the opponent does not move next.
+    pub fn threats_after(&self, pos: &Coord, piece: Piece) -> Vec<Coord> {
+        if self.five_if_placed(pos, piece).is_some() {
+            return Vec::new();
+        }
+        let opponent = piece.next();
+        (0..(self.width * self.width) as usize)
+            .filter(|idx| !self.pieces.contains_key(idx))
+            .map(|idx| self.idx2pos(idx))
+            .filter(|cell| cell != pos)
+            .filter(|cell| self.find_five_in_a_row(cell, opponent, Some(pos)).is_some())
+            .collect()
+    }
+    fn line2pos(&self, line: &[usize; 5]) -> [Coord; 5] {
+        [
+            self.idx2pos(line[0]),
+            self.idx2pos(line[1]),
+            self.idx2pos(line[2]),
+            self.idx2pos(line[3]),
+            self.idx2pos(line[4]),
+        ]
+    }
     fn calc_five_in_a_row(&self, pos: &Coord) -> Option<[usize; 5]> {
-        for (dx, dy) in &[(1, 0), (0, 1), (1, 1), (1, -1)] {
-            let count0 = self.count_ours(pos, -*dx, -*dy);
-            let count1 = self.count_ours(pos, *dx, *dy);
+        let piece = *self.get_piece(pos)?;
+        let line = self.find_five_in_a_row(pos, piece, None)?;
+        info!("find five in row: {:?}", line);
+        Some(line)
+    }
+    /// Looks for a five-in-a-row through `pos` as if `piece` were there,
+    /// treating `blocked` as not ours whatever is on it.
+    fn find_five_in_a_row(
+        &self,
+        pos: &Coord,
+        piece: Piece,
+        blocked: Option<&Coord>,
+    ) -> Option<[usize; 5]> {
+        for (dx, dy) in DIRECTIONS.iter() {
+            let count0 = self.count_ours(pos, piece, blocked, -*dx, -*dy);
+            let count1 = self.count_ours(pos, piece, blocked, *dx, *dy);
             if count0 + count1 + 1 >= 5 {
                 let start = Coord::new_bounded(pos.x - dx * count0, pos.y - dy * count0);
                 let mut line = [0; 5];
@@ -139,25 +228,32 @@ impl Board {
                     pos.x += dx;
                     pos.y += dy;
                 }
-                info!("find five in row: {:?}", line);
                 return Some(line);
             }
         }
         None
     }
-    fn count_ours(&self, pos: &Coord, dx: i32, dy: i32) -> i32 {
+    fn count_ours(
+        &self,
+        pos: &Coord,
+        piece: Piece,
+        blocked: Option<&Coord>,
+        dx: i32,
+        dy: i32,
+    ) -> i32 {
         let mut count = 0;
-        if let Some(piece) = self.get_piece(pos) {
-            let Coord { mut x, mut y, .. } = *pos;
-            loop {
-                x += dx;
-                y += dy;
-                let pos = Coord::new_bounded(x, y);
-                if self.out_of_bound(x, y) || self.get_piece(&pos) != Some(piece) {
-                    break;
-                }
-                count += 1;
+        let Coord { mut x, mut y, .. } = *pos;
+        loop {
+            x += dx;
+            y += dy;
+            let pos = Coord::new_bounded(x, y);
+            if self.out_of_bound(x, y)
+                || self.get_piece(&pos) != Some(&piece)
+                || blocked == Some(&pos)
+            {
+                break;
             }
+            count += 1;
         }
         count
     }
@@ -198,48 +294,66 @@ pub trait PieceRender {
 
 pub struct PieceSystem;
 
-impl PieceRender for PieceSystem {}
-
 impl<'a> System<'a> for PieceSystem {
     type SystemData = (
-        Entities<'a>,
-        ReadExpect<'a, SpriteRender>,
-        ReadExpect<'a, Transform>,
         WriteExpect<'a, Board>,
+        Write<'a, EventChannel<GameEvent>>,
         WriteStorage<'a, WantsToPlacePiece>,
-        WriteStorage<'a, Piece>,
-        WriteStorage<'a, SpriteRender>,
-        WriteStorage<'a, Transform>,
     );
 
-    fn run(&mut self, data: Self::SystemData) {
-        let (
-            entities,
-            renderer,
-            default_trans,
-            mut board,
-            mut pieces,
-            mut piece_storage,
-            mut render_storage,
-            mut transform_storage,
-        ) = data;
+    fn run(&mut self, (mut board, mut events, mut pieces): Self::SystemData) {
         for (piece,) in (&mut pieces,).join() {
             if board.set_piece(&piece.pos, piece.piece) {
-                let mut renderer = (*renderer).clone();
-                Self::setup_renderer(&mut renderer, piece.piece.idx());
-                let transform = Self::setup_transform(&*default_trans, &piece.pos);
-                board.put_entity(
-                    &piece.pos,
-                    entities
-                        .build_entity()
-                        .with(piece.piece, &mut piece_storage)
-                        .with(renderer, &mut render_storage)
-                        .with(transform, &mut transform_storage)
-                        .build(),
-                );
-                info!("put a {:?} entity at {}", piece.piece, piece.pos);
+                info!("put a {:?} piece at {}", piece.piece, piece.pos);
+                events.single_write(GameEvent::PiecePlaced(piece.piece, piece.pos));
             }
         }
         pieces.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A board with white stones at `white` and black ones at `black`.
+    fn board(white: &[(i32, i32)], black: &[(i32, i32)]) -> Board {
+        let mut board = Board::new(BOARD_HALF_WIDTH);
+        for (stones, piece) in [(white, Piece::White), (black, Piece::Black)].iter() {
+            for (x, y) in stones.iter() {
+                board.put_piece(&Coord::new_bounded(*x, *y), *piece);
+            }
+        }
+        board
+    }
+
+    fn cells(cells: &[(i32, i32)]) -> Vec<Coord> {
+        cells
+            .iter()
+            .map(|(x, y)| Coord::new_bounded(*x, *y))
+            .collect()
+    }
+
+    const FOUR: [(i32, i32); 4] = [(0, 0), (1, 0), (2, 0), (3, 0)];
+
+    #[test]
+    fn threats_of_a_four_left_open_are_warned() {
+        let board = board(&FOUR, &[]);
+        let threats = board.threats_after(&Coord::new_bounded(5, 5), Piece::Black);
+        assert_eq!(threats, cells(&[(-1, 0), (4, 0)]));
+    }
+
+    #[test]
+    fn threats_of_a_four_blocked_by_the_move_are_not_warned() {
+        let board = board(&FOUR, &[(-1, 0)]);
+        let threats = board.threats_after(&Coord::new_bounded(4, 0), Piece::Black);
+        assert!(threats.is_empty());
+    }
+
+    #[test]
+    fn threats_are_not_warned_when_the_move_completes_a_five() {
+        let board = board(&FOUR, &[(0, 2), (1, 2), (2, 2), (3, 2)]);
+        let threats = board.threats_after(&Coord::new_bounded(4, 2), Piece::Black);
+        assert!(threats.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use amethyst::ecs::*;
+use amethyst::ui::{Anchor, LineMode, UiText, UiTransform};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+
+use crate::board::Piece;
+use crate::marker::Markers;
+
+const CHAT_FONT_SIZE: f32 = 13.0;
+/// How many of the last lines the panel shows.
+const CHAT_LINES: usize = 6;
+/// Longer messages are cut, here and by whoever relays them.
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// A canned message, sent with a single key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Emote {
+    Hello,
+    GoodMove,
+    Oops,
+    GoodGame,
+}
+
+impl Emote {
+    /// The emote of a number key, from 1.
+    pub fn from_number(number: usize) -> Option<Self> {
+        [Emote::Hello, Emote::GoodMove, Emote::Oops, Emote::GoodGame]
+            .get(number.checked_sub(1)?)
+            .cloned()
+    }
+}
+
+impl Display for Emote {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Emote::Hello => write!(f, "*waves hello*"),
+            Emote::GoodMove => write!(f, "*nods: good move*"),
+            Emote::Oops => write!(f, "*oops*"),
+            Emote::GoodGame => write!(f, "*good game*"),
+        }
+    }
+}
+
+/// Cuts `text` to `MAX_CHAT_LEN` characters, on a character boundary.
+pub fn truncate(text: &str) -> String {
+    text.chars().take(MAX_CHAT_LEN).collect()
+}
+
+/// What the players said during a networked game, and the line being
+/// typed. With `muted` on, the other side's messages are still recorded,
+/// but not shown.
+#[derive(Default)]
+pub struct Chat {
+    /// Who said what, and whether it was said on this machine.
+    lines: VecDeque<(Piece, String, bool)>,
+    /// The message being typed, once chat was opened.
+    pub input: Option<String>,
+    pub muted: bool,
+}
+
+impl Chat {
+    pub fn push(&mut self, side: Piece, text: String, ours: bool) {
+        self.lines.push_back((side, text, ours));
+        if self.lines.len() > CHAT_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut text: Vec<_> = self
+            .lines
+            .iter()
+            .filter(|(.., ours)| !self.muted || *ours)
+            .map(|(side, line, _)| format!("{:?}: {}", side, line))
+            .collect();
+        if self.muted {
+            text.push("(chat muted, M to unmute)".to_string());
+        }
+        if let Some(input) = &self.input {
+            text.push(format!("> {}_", input));
+        }
+        text.join("\n")
+    }
+}
+
+pub struct ChatPanel {
+    text: Entity,
+}
+
+/// Must run after `initialize_markers`, whose font it borrows.
+pub fn initialize_chat(world: &mut World) {
+    let font = world.fetch::<Markers>().font.clone();
+    let ui_transform = UiTransform::new(
+        "chat".to_string(),
+        Anchor::BottomLeft,
+        Anchor::BottomLeft,
+        0.01,
+        0.01,
+        2.0,
+        0.6,
+        0.2,
+    )
+    .into_percent()
+    .into_transparent();
+    let ui_text = UiText::new(
+        font,
+        String::new(),
+        [1.0, 1.0, 0.8, 1.0],
+        CHAT_FONT_SIZE,
+        LineMode::Wrap,
+        Anchor::BottomLeft,
+    );
+    let text = world
+        .create_entity()
+        .with(ui_transform)
+        .with(ui_text)
+        .build();
+    world.insert(ChatPanel { text });
+    world.insert(Chat::default());
+}
+
+/// Shows the chat in its panel.
+pub struct ChatSystem;
+
+impl<'a> System<'a> for ChatSystem {
+    type SystemData = (
+        ReadExpect<'a, ChatPanel>,
+        Read<'a, Chat>,
+        WriteStorage<'a, UiText>,
+    );
+
+    fn run(&mut self, (panel, chat, mut ui_texts): Self::SystemData) {
+        if let Some(ui_text) = ui_texts.get_mut(panel.text) {
+            let text = chat.text();
+            if ui_text.text != text {
+                ui_text.text = text;
+            }
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use amethyst::config::Config;
+use amethyst::Error;
+use log::{info, warn};
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use tungstenite::Message as WsMessage;
+
+use crate::ai::{run_search, AiConfig, Book, Engine, XorShift};
+use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+use crate::cursor::Coord;
+use crate::net::{
+    default_name, find_games, resolve, Announcement, NetConfig, Request, Server, Update,
+    DISCOVERY_WAIT, LOOPBACK,
+};
+use crate::notation;
+use crate::rules::Position;
+use crate::selfplay::{decode, random_opening, run_match, Brain, EnginePlayer, Player};
+use crate::tactics::Suite;
+use crate::Turn;
+
+/// Moves played at random at the start of a self-play game, so that the
+/// games differ.
+const RANDOM_PLIES: usize = 2;
+
+/// Runs a subcommand given on the command line instead of the game.
+pub fn run(command: &str, args: &[String], app_root: &Path) -> amethyst::Result<()> {
+    let config_dir = &app_root.join("config");
+    match command {
+        "book" => build_book(args, config_dir),
+        "match" => play_match(args, config_dir),
+        "brain" => brain(args, config_dir),
+        "tactics" => solve_tactics(args, app_root),
+        "server" => serve(args, config_dir),
+        "lobby" => list_games(args),
+        "bot" => bot(args, config_dir),
+        "convert" => convert(args),
+        _ => Err(Error::from_string(format!(
+            "unknown command {}, expected one of: host, join, watch, server, lobby, bot, convert, book, match, brain, tactics",
+            command
+        ))),
+    }
+}
+
+/// `book [--games N] [--plies N] [--out FILE] [RECORD...]`
+///
+/// Adds the first moves of `N` self-play games and of the given game
+/// records, in any format `convert` reads, to the opening book, which is
+/// created if it does not exist yet.
+fn build_book(args: &[String], config_dir: &Path) -> amethyst::Result<()> {
+    let config = AiConfig::load(config_dir.join("ai.ron"))?;
+    let mut games = 0;
+    let mut plies = 8;
+    let mut out = config_dir.join(config.book.as_deref().unwrap_or("book.ron"));
+    let mut records = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => games = parse_value(arg, args.next())?,
+            "--plies" => plies = parse_value(arg, args.next())?,
+            "--out" => out = PathBuf::from(parse_value::<String>(arg, args.next())?),
+            _ => records.push(PathBuf::from(arg)),
+        }
+    }
+
+    let mut book = Book::load(&out).unwrap_or_else(|err| {
+        warn!("starting a new book, unable to load {:?}: {}", out, err);
+        Book::default()
+    });
+    for path in records {
+        let record = notation::load(&path)?;
+        let moves = record
+            .moves()
+            .map_err(|err| Error::from_string(format!("{:?}: {}", path, err)))?;
+        for (position, pos) in moves.iter().take(plies) {
+            book.add(position, pos);
+        }
+        info!("added {:?}", path);
+    }
+    let mut rng = XorShift::from_time();
+    let mut engine = Engine::new(config);
+    for game in 0..games {
+        let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+        let opening = random_opening(&mut rng, RANDOM_PLIES.min(plies));
+        for ply in 0..plies {
+            let pos = match opening.get(ply) {
+                Some(pos) => *pos,
+                None => match engine.search(position.clone(), |_| {}).best {
+                    Some(pos) => pos,
+                    None => break,
+                },
+            };
+            book.add(&position, &pos);
+            position.play(&pos).expect("engine played an illegal move");
+        }
+        info!("self-play game {} of {} done", game + 1, games);
+    }
+    book.write(&out)?;
+    info!("{} positions written to {:?}", book.positions.len(), out);
+    Ok(())
+}
+
+/// `convert IN OUT`
+///
+/// Rewrites a game record in the format of `OUT`'s extension: `.ron` for our
+/// own, `.psq` for Piskvork, or `.txt` for moves such as `h8`. The moves are
+/// replayed by the rules on the way, so a record of an impossible game is
+/// refused.
+fn convert(args: &[String]) -> amethyst::Result<()> {
+    let (from, to) = match args {
+        [from, to] => (Path::new(from), Path::new(to)),
+        _ => {
+            return Err(Error::from_string(
+                "convert takes a record and where to write it",
+            ))
+        }
+    };
+    let record = notation::load(from)?;
+    notation::save(&record, to)?;
+    info!("{:?} written to {:?}", from, to);
+    Ok(())
+}
+
+/// `match [--games N] [--opening N] [--max-moves N] FIRST SECOND`
+///
+/// Plays the two players against each other and prints how the first one
+/// did. A player is either an engine config file like `config/ai.ron`, or
+/// `exec:` followed by the command starting a brain, such as
+/// `"exec:../old/endless-fir brain"` to test against another build.
+fn play_match(args: &[String], config_dir: &Path) -> amethyst::Result<()> {
+    let mut games = 10;
+    let mut opening = RANDOM_PLIES;
+    let mut max_moves = 200;
+    let mut players = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => games = parse_value(arg, args.next())?,
+            "--opening" => opening = parse_value(arg, args.next())?,
+            "--max-moves" => max_moves = parse_value(arg, args.next())?,
+            _ => players.push(load_player(arg, config_dir)?),
+        }
+    }
+    if players.len() != 2 {
+        return Err(Error::from_string("match expects two players"));
+    }
+    let mut second = players.pop().expect("no second player");
+    let mut first = players.pop().expect("no first player");
+    let stats = run_match(&mut *first, &mut *second, games, opening, max_moves);
+    println!("{} against {}", first.name(), second.name());
+    println!("{}", stats);
+    Ok(())
+}
+
+fn load_player(spec: &str, config_dir: &Path) -> amethyst::Result<Box<dyn Player>> {
+    if let Some(command) = spec.strip_prefix("exec:") {
+        return Ok(Box::new(Brain::spawn(command)?));
+    }
+    let path = Path::new(spec);
+    let config = AiConfig::load(path)?;
+    let book = match &config.book {
+        Some(file) => Book::load(path.parent().unwrap_or(config_dir).join(file))?,
+        None => Book::default(),
+    };
+    Ok(Box::new(EnginePlayer::new(spec.to_string(), config, book)))
+}
+
+/// `brain [CONFIG]`
+///
+/// Answers positions on stdin with the engine's moves on stdout, as
+/// described for `Brain`, so that `match` can play against this build.
+fn brain(args: &[String], config_dir: &Path) -> amethyst::Result<()> {
+    let path = match args.first() {
+        Some(path) => PathBuf::from(path),
+        None => config_dir.join("ai.ron"),
+    };
+    let config = AiConfig::load(path)?;
+    let mut engine = Engine::new(config.clone());
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line == "quit" {
+            break;
+        }
+        let position = match line.strip_prefix("go ").and_then(decode) {
+            Some(position) => position,
+            None => {
+                warn!("unable to read {:?}", line);
+                writeln!(stdout, "error")?;
+                stdout.flush()?;
+                continue;
+            }
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        match run_search(&mut engine, position, config.clone(), stop, |_| {}).best {
+            Some(pos) => writeln!(stdout, "move {},{}", pos.x, pos.y)?,
+            None => writeln!(stdout, "error")?,
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// `server [ADDRESS[:PORT]] [NAME]`
+///
+/// Keeps a game for clients that `join` it, without a window, at
+/// `127.0.0.1` unless given an address such as `0.0.0.0` to serve the local
+/// network.
+fn serve(args: &[String], config_dir: &Path) -> amethyst::Result<()> {
+    let addr = resolve(args.first().map_or(LOOPBACK, String::as_str))?;
+    let name = args.get(1).cloned().unwrap_or_else(default_name);
+    let config = NetConfig::load(config_dir.join("net.ron"))?;
+    Server::bind(addr, name, &config)?.run();
+    Ok(())
+}
+
+/// `lobby [SECONDS]`
+///
+/// Lists the games announced on the local network.
+fn list_games(args: &[String]) -> amethyst::Result<()> {
+    let wait = match args.first() {
+        Some(_) => Duration::from_secs(parse_value("lobby", args.first())?),
+        None => DISCOVERY_WAIT,
+    };
+    let games = find_games(wait)?;
+    for (addr, game) in games.iter() {
+        println!("{}", describe(addr, game));
+    }
+    if games.is_empty() {
+        println!("no games found");
+    }
+    Ok(())
+}
+
+/// Lists the games announced on the local network, and asks on the
+/// terminal which one to join, or to watch, when not given an address.
+pub fn choose_game(watch: bool) -> amethyst::Result<SocketAddr> {
+    println!("looking for games on the local network...");
+    let games = find_games(DISCOVERY_WAIT)?;
+    if games.is_empty() {
+        return Err(Error::from_string("no game found on the local network"));
+    }
+    for (idx, (addr, game)) in games.iter().enumerate() {
+        println!("{:>2}. {}", idx + 1, describe(addr, game));
+    }
+    let stdin = std::io::stdin();
+    loop {
+        print!(
+            "{} which game? [1-{}, or q to quit] ",
+            if watch { "watch" } else { "join" },
+            games.len()
+        );
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 || line.trim() == "q" {
+            return Err(Error::from_string("no game chosen"));
+        }
+        match line.trim().parse::<usize>() {
+            Ok(choice) if choice >= 1 && choice <= games.len() => {
+                let (addr, game) = &games[choice - 1];
+                if game.is_full() && !watch {
+                    println!("{:?} has two players already, so you will watch", game.name);
+                }
+                return Ok(*addr);
+            }
+            _ => println!("expected a number from 1 to {}", games.len()),
+        }
+    }
+}
+
+fn describe(addr: &SocketAddr, game: &Announcement) -> String {
+    format!(
+        "{:<21}  {}  {}x{} {}, {}/2 players, {} watching",
+        addr.to_string(),
+        game.name,
+        game.board_size,
+        game.board_size,
+        game.variant,
+        game.players,
+        game.spectators
+    )
+}
+
+/// `bot [--config FILE] [--side black|white] [URL]`
+///
+/// Plays a side, white by default, with the engine through the WebSocket
+/// API of a running game, at `ws://127.0.0.1:` and the `api_port` of
+/// `config/net.ron` unless given a URL. Mostly to try the API out.
+fn bot(args: &[String], config_dir: &Path) -> amethyst::Result<()> {
+    let mut config_path = config_dir.join("ai.ron");
+    let mut side = Piece::White;
+    let mut url = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = PathBuf::from(parse_value::<String>(arg, args.next())?),
+            "--side" => {
+                side = match parse_value::<String>(arg, args.next())?.as_str() {
+                    "black" => Piece::Black,
+                    "white" => Piece::White,
+                    other => {
+                        return Err(Error::from_string(format!(
+                            "unknown side {}, expected black or white",
+                            other
+                        )))
+                    }
+                }
+            }
+            _ => url = Some(arg.clone()),
+        }
+    }
+    let url = match url {
+        Some(url) => url,
+        None => {
+            let port = NetConfig::load(config_dir.join("net.ron"))?
+                .api_port
+                .ok_or_else(|| Error::from_string("the API is not served, see config/net.ron"))?;
+            format!("ws://127.0.0.1:{}", port)
+        }
+    };
+    let config = AiConfig::load(config_path)?;
+    let mut engine = Engine::new(config.clone());
+    let (mut socket, _) = tungstenite::connect(url.as_str())
+        .map_err(|err| Error::from_string(format!("unable to connect to {}: {}", url, err)))?;
+    info!("playing {:?} at {}", side, url);
+    loop {
+        let text = match socket.read_message() {
+            Ok(WsMessage::Text(text)) => text,
+            Ok(WsMessage::Close(_)) => break,
+            Ok(_) => continue,
+            Err(err) => return Err(Error::from_string(format!("lost {}: {}", url, err))),
+        };
+        let update = match serde_json::from_str(&text) {
+            Ok(update) => update,
+            Err(err) => {
+                warn!("unreadable update {:?}: {}", text, err);
+                continue;
+            }
+        };
+        let (stones, bonus) = match update {
+            Update::State {
+                stones,
+                side: to_move,
+                bonus,
+                over,
+            } if to_move == side && !over => (stones, bonus),
+            Update::Rejected { reason } => {
+                warn!("rejected: {}", reason);
+                continue;
+            }
+            _ => continue,
+        };
+        let mut board = Board::new(BOARD_HALF_WIDTH);
+        for stone in stones {
+            board.put_piece(&Coord::new_bounded(stone.x, stone.y), stone.piece);
+        }
+        let position = Position::new(board, side.turn(), bonus);
+        let stop = Arc::new(AtomicBool::new(false));
+        let pos = match run_search(&mut engine, position.clone(), config.clone(), stop, |_| {}).best
+        {
+            Some(pos) => pos,
+            None => {
+                warn!("no move found");
+                continue;
+            }
+        };
+        let request = match position.check(&pos) {
+            Ok(Some(_)) => Request::Capture { x: pos.x, y: pos.y },
+            _ => Request::Place { x: pos.x, y: pos.y },
+        };
+        info!("playing {:?}", request);
+        let text = serde_json::to_string(&request).expect("unable to encode request");
+        socket
+            .write_message(WsMessage::Text(text))
+            .map_err(|err| Error::from_string(format!("lost {}: {}", url, err)))?;
+    }
+    Ok(())
+}
+
+/// `tactics [--config FILE] [SUITE]`
+///
+/// Runs the engine on every position of the suite, `tactics/suite.ron` by
+/// default, and fails unless it solves them all.
+fn solve_tactics(args: &[String], app_root: &Path) -> amethyst::Result<()> {
+    let mut config_path = app_root.join("config").join("ai.ron");
+    let mut suite_path = app_root.join("tactics").join("suite.ron");
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = PathBuf::from(parse_value::<String>(arg, args.next())?),
+            _ => suite_path = PathBuf::from(arg),
+        }
+    }
+    let config = AiConfig::load(config_path)?;
+    let suite = Suite::load(&suite_path)?;
+    let mut passed = 0;
+    let mut solve_time = Duration::default();
+    let mut engine = Engine::new(config.clone());
+    for tactic in suite.tactics.iter() {
+        let outcome = tactic
+            .run(&mut engine, &config)
+            .map_err(Error::from_string)?;
+        println!("{}", outcome);
+        if let Some(solved) = outcome.solved {
+            passed += 1;
+            solve_time += solved;
+        }
+    }
+    let total = suite.tactics.len();
+    println!(
+        "{} of {} solved ({:.0}%), {:.2}s on average to solve",
+        passed,
+        total,
+        passed as f32 * 100.0 / total.max(1) as f32,
+        solve_time.as_secs_f32() / passed.max(1) as f32
+    );
+    if passed < total {
+        return Err(Error::from_string(format!(
+            "{} of {} tactics failed",
+            total - passed,
+            total
+        )));
+    }
+    Ok(())
+}
+
+pub fn parse_value<T: std::str::FromStr>(
+    flag: &str,
+    value: Option<&String>,
+) -> amethyst::Result<T> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| Error::from_string(format!("{} expects a value", flag)))
+}
@@ -0,0 +1,613 @@
+use amethyst::ecs::*;
+use amethyst::shrev::{EventChannel, ReaderId};
+use amethyst::Error;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, Piece};
+use crate::event::GameEvent;
+use crate::{BonusTurn, GameOver, Turn};
+
+/// How a side's time is given.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub enum TimeControl {
+    /// `main` seconds for the whole game.
+    SuddenDeath { main: f32 },
+    /// `main` seconds, and `increment` more after each move.
+    Fischer { main: f32, increment: f32 },
+    /// `main` seconds, then `periods` periods of `period` seconds. A move
+    /// made within a period keeps it for the next move, and a period run
+    /// out is gone.
+    Byoyomi {
+        main: f32,
+        periods: u32,
+        period: f32,
+    },
+}
+
+impl TimeControl {
+    fn main(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { main }
+            | TimeControl::Fischer { main, .. }
+            | TimeControl::Byoyomi { main, .. } => Duration::from_secs_f32(main),
+        }
+    }
+
+    fn periods(&self) -> u32 {
+        match *self {
+            TimeControl::Byoyomi { periods, .. } => periods,
+            _ => 0,
+        }
+    }
+
+    /// Checks that every time is one a clock can keep, and that byoyomi
+    /// periods are long enough to count.
+    pub fn validate(&self) -> Result<(), Error> {
+        match *self {
+            TimeControl::SuddenDeath { main } => check_seconds("main", main),
+            TimeControl::Fischer { main, increment } => {
+                check_seconds("main", main)?;
+                check_seconds("increment", increment)
+            }
+            TimeControl::Byoyomi {
+                main,
+                periods,
+                period,
+            } => {
+                check_seconds("main", main)?;
+                check_seconds("period", period)?;
+                if periods > 0 && Duration::from_secs_f32(period) == Duration::default() {
+                    return Err(Error::from_string(format!(
+                        "a byoyomi period of {} seconds is too short",
+                        period
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Checks that `seconds` is a time a `Duration` can be made of.
+fn check_seconds(name: &str, seconds: f32) -> Result<(), Error> {
+    if seconds.is_finite() && seconds >= 0.0 && seconds < u64::MAX as f32 {
+        Ok(())
+    } else {
+        Err(Error::from_string(format!(
+            "{} is {} seconds, expected a time of 0 or more",
+            name, seconds
+        )))
+    }
+}
+
+/// What a bonus turn does to the clock of the side playing it.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub enum BonusTime {
+    /// The clock runs on, as if the bonus turn were part of the move that
+    /// earned it.
+    Share,
+    /// The clock stops until the bonus turn is played. The increment, or
+    /// the byoyomi period, is still that of a single move.
+    Pause,
+    /// The bonus turn is a move of its own, with its own increment or
+    /// byoyomi period.
+    Add,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    /// Black's time control, `None` for no clock.
+    pub black: Option<TimeControl>,
+    pub white: Option<TimeControl>,
+    pub bonus_time: BonusTime,
+    /// Seconds left below which a clock is shown as running low.
+    pub low_time: f32,
+}
+
+impl ClockConfig {
+    /// Checks both time controls and `low_time`, which would otherwise
+    /// panic once the clock runs.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (side, control) in [("black", &self.black), ("white", &self.white)].iter() {
+            if let Some(control) = control {
+                control.validate().map_err(|err| {
+                    Error::from_string(format!("{}'s time control: {}", side, err))
+                })?;
+            }
+        }
+        check_seconds("low_time", self.low_time)
+    }
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            black: None,
+            white: None,
+            bonus_time: BonusTime::Share,
+            low_time: 10.0,
+        }
+    }
+}
+
+/// Where a clock reads the time from. An `Instant` tells the time since it
+/// was taken, and anything else, such as a fake time moved by hand, can
+/// stand in for it.
+pub trait TimeSource {
+    fn now(&self) -> Duration;
+}
+
+impl TimeSource for Instant {
+    fn now(&self) -> Duration {
+        self.elapsed()
+    }
+}
+
+/// What a side has left.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeLeft {
+    pub main: Duration,
+    /// Byoyomi periods left, counting the current one.
+    pub periods: u32,
+    /// What is left of the current byoyomi period, once in byoyomi.
+    pub period: Duration,
+}
+
+impl TimeLeft {
+    /// Everything left until the flag falls.
+    pub fn total(&self, control: &TimeControl) -> Duration {
+        match *control {
+            TimeControl::Byoyomi { period, .. } if self.periods > 0 => {
+                let whole = Duration::from_secs_f32(period) * (self.periods - 1);
+                self.main + whole + self.period
+            }
+            _ => self.main,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SideTime {
+    /// Main time left at the start of the turn.
+    main: Duration,
+    /// Byoyomi periods left at the start of the turn.
+    periods: u32,
+    /// Time spent in this turn, but for the part still running.
+    spent: Duration,
+}
+
+/// Both sides' clocks. The clock of the side to move runs while it thinks,
+/// and stops while a five-in-a-row blinks.
+pub struct Clock<T = Instant> {
+    time: T,
+    config: ClockConfig,
+    sides: [SideTime; 2],
+    /// The side whose clock runs, and since when.
+    running: Option<(Piece, Duration)>,
+}
+
+impl<T: TimeSource> Clock<T> {
+    pub fn new(config: ClockConfig, time: T) -> Self {
+        let mut clock = Self {
+            time,
+            config,
+            sides: Default::default(),
+            running: None,
+        };
+        clock.reset();
+        clock
+    }
+
+    pub fn control(&self, side: Piece) -> Option<&TimeControl> {
+        match side {
+            Piece::Black => self.config.black.as_ref(),
+            Piece::White => self.config.white.as_ref(),
+        }
+    }
+
+    /// Stops `side`'s clock after a move completing a five-in-a-row, which
+    /// earns a bonus turn rather than ending the turn, unless bonus turns
+    /// are moves of their own.
+    pub fn earn_bonus(&mut self, side: Piece) {
+        match self.config.bonus_time {
+            BonusTime::Add => self.end_turn(side),
+            BonusTime::Share | BonusTime::Pause => self.pause(),
+        }
+    }
+
+    /// Whether the clock runs while a bonus turn is played.
+    pub fn runs_in_bonus(&self) -> bool {
+        self.config.bonus_time != BonusTime::Pause
+    }
+
+    /// Sets both clocks back for a new game.
+    pub fn reset(&mut self) {
+        self.running = None;
+        for side in [Piece::Black, Piece::White].iter() {
+            let (main, periods) = self
+                .control(*side)
+                .map_or((Duration::default(), 0), |control| {
+                    (control.main(), control.periods())
+                });
+            self.sides[side.idx()] = SideTime {
+                main,
+                periods,
+                spent: Duration::default(),
+            };
+        }
+    }
+
+    pub fn running(&self) -> Option<Piece> {
+        self.running.map(|(side, _)| side)
+    }
+
+    /// Starts `side`'s clock, stopping the other one.
+    pub fn start(&mut self, side: Piece) {
+        if self.running() == Some(side) {
+            return;
+        }
+        self.pause();
+        self.running = Some((side, self.time.now()));
+    }
+
+    /// Stops the running clock within a turn, which goes on when it is
+    /// started again.
+    pub fn pause(&mut self) {
+        if let Some((side, since)) = self.running.take() {
+            self.sides[side.idx()].spent += self.since(since);
+        }
+    }
+
+    fn since(&self, since: Duration) -> Duration {
+        self.time.now().checked_sub(since).unwrap_or_default()
+    }
+
+    /// Stops `side`'s clock at the end of its turn, and gives it the
+    /// increment, or its byoyomi period back.
+    pub fn end_turn(&mut self, side: Piece) {
+        if self.running() == Some(side) {
+            self.pause();
+        }
+        let left = self.left(side);
+        let control = self.control(side).cloned();
+        let time = &mut self.sides[side.idx()];
+        time.spent = Duration::default();
+        match control {
+            Some(TimeControl::Fischer { increment, .. }) if left.main > Duration::default() => {
+                time.main = left.main + Duration::from_secs_f32(increment);
+            }
+            Some(_) => {
+                time.main = left.main;
+                time.periods = left.periods;
+            }
+            None => {}
+        }
+    }
+
+    /// What `side` has left, counting the running turn.
+    pub fn left(&self, side: Piece) -> TimeLeft {
+        let time = &self.sides[side.idx()];
+        let mut spent = time.spent;
+        if let Some((running, since)) = self.running {
+            if running == side {
+                spent += self.since(since);
+            }
+        }
+        let control = match self.control(side) {
+            Some(control) => control,
+            None => {
+                return TimeLeft {
+                    main: Duration::default(),
+                    periods: 0,
+                    period: Duration::default(),
+                }
+            }
+        };
+        if spent <= time.main {
+            let period = match *control {
+                TimeControl::Byoyomi { period, .. } => Duration::from_secs_f32(period),
+                _ => Duration::default(),
+            };
+            return TimeLeft {
+                main: time.main - spent,
+                periods: time.periods,
+                period,
+            };
+        }
+        let over = spent - time.main;
+        let period = match *control {
+            TimeControl::Byoyomi { period, .. } => Duration::from_secs_f32(period),
+            _ => Duration::default(),
+        };
+        // Also no period for one too short to count in nanoseconds.
+        if period == Duration::default() {
+            return TimeLeft {
+                main: Duration::default(),
+                periods: 0,
+                period: Duration::default(),
+            };
+        }
+        let used = (over.as_nanos() / period.as_nanos()) as u32;
+        let periods = time.periods.saturating_sub(used);
+        let period = if periods > 0 {
+            period - Duration::from_nanos((over.as_nanos() % period.as_nanos()) as u64)
+        } else {
+            Duration::default()
+        };
+        TimeLeft {
+            main: Duration::default(),
+            periods,
+            period,
+        }
+    }
+
+    /// The side whose time ran out, if any.
+    pub fn flagged(&self) -> Option<Piece> {
+        let side = self.running()?;
+        let control = self.control(side)?;
+        if self.left(side).total(control) == Duration::default() {
+            Some(side)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_low(&self, side: Piece) -> bool {
+        self.control(side).map_or(false, |control| {
+            self.left(side).total(control) < Duration::from_secs_f32(self.config.low_time)
+        })
+    }
+
+    /// The clock of `side` as shown to the players, empty without one.
+    pub fn text(&self, side: Piece) -> String {
+        let control = match self.control(side) {
+            Some(control) => control,
+            None => return String::new(),
+        };
+        let left = self.left(side);
+        let time = match control {
+            TimeControl::Byoyomi { .. } if left.main == Duration::default() => {
+                format!("{} ({} left)", format_time(left.period), left.periods)
+            }
+            TimeControl::Byoyomi { period, .. } => {
+                format!("{} + {}x{}s", format_time(left.main), left.periods, period)
+            }
+            _ => format_time(left.main),
+        };
+        format!("{:?} {}", side, time)
+    }
+}
+
+/// Minutes and seconds, with tenths under ten seconds.
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs < 10 {
+        format!("{}.{}", secs, time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Runs the clock of the side to move, and hands over at the end of each
+/// turn. Only keeps time: the flag is checked by the game.
+#[derive(Default)]
+pub struct ClockSystem {
+    reader: Option<ReaderId<GameEvent>>,
+    /// Whether a five-in-a-row is blinking, during which no clock runs.
+    blinking: bool,
+}
+
+impl<'a> System<'a> for ClockSystem {
+    type SystemData = (
+        Read<'a, EventChannel<GameEvent>>,
+        ReadExpect<'a, Board>,
+        ReadExpect<'a, Turn>,
+        ReadExpect<'a, BonusTurn>,
+        ReadExpect<'a, GameOver>,
+        WriteExpect<'a, Clock>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (events, board, turn, bonus, game_over, mut clock): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("clock system is not set up");
+        for event in events.read(reader) {
+            match *event {
+                // A move completing a five-in-a-row earns a bonus turn
+                // rather than ending the turn.
+                GameEvent::PiecePlaced(piece, _) if board.has_five_in_a_row() => {
+                    clock.earn_bonus(piece)
+                }
+                GameEvent::PiecePlaced(piece, _) => clock.end_turn(piece),
+                GameEvent::FiveInARow(..) => {
+                    self.blinking = true;
+                    clock.pause();
+                }
+                GameEvent::FiveRemoved(..) => self.blinking = false,
+                GameEvent::NewGame => clock.reset(),
+                GameEvent::Undone | GameEvent::Restored | GameEvent::GameOver => clock.pause(),
+                GameEvent::Captured(..)
+                | GameEvent::InvalidClick(..)
+                | GameEvent::HintShown(..) => {}
+            }
+        }
+        let paused = bonus.0 && !clock.runs_in_bonus();
+        if game_over.0 || self.blinking || board.has_five_in_a_row() || paused {
+            clock.pause();
+        } else {
+            clock.start(turn.piece());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    impl TimeSource for &Cell<Duration> {
+        fn now(&self) -> Duration {
+            self.get()
+        }
+    }
+
+    fn config(control: TimeControl, bonus_time: BonusTime) -> ClockConfig {
+        ClockConfig {
+            black: Some(control),
+            white: Some(control),
+            bonus_time,
+            ..ClockConfig::default()
+        }
+    }
+
+    fn advance(time: &Cell<Duration>, secs: f32) {
+        time.set(time.get() + Duration::from_secs_f32(secs));
+    }
+
+    fn secs(secs: f32) -> Duration {
+        Duration::from_secs_f32(secs)
+    }
+
+    #[test]
+    fn sudden_death_flag_falls() {
+        let time = Cell::new(Duration::default());
+        let control = TimeControl::SuddenDeath { main: 10.0 };
+        let mut clock = Clock::new(config(control, BonusTime::Share), &time);
+        clock.start(Piece::Black);
+        advance(&time, 9.5);
+        assert_eq!(clock.left(Piece::Black).main, secs(0.5));
+        assert_eq!(clock.flagged(), None);
+        // White's clock does not run meanwhile.
+        assert_eq!(clock.left(Piece::White).main, secs(10.0));
+        advance(&time, 1.0);
+        assert_eq!(clock.left(Piece::Black).main, Duration::default());
+        assert_eq!(clock.flagged(), Some(Piece::Black));
+    }
+
+    #[test]
+    fn fischer_increment_only_while_time_is_left() {
+        let time = Cell::new(Duration::default());
+        let control = TimeControl::Fischer {
+            main: 10.0,
+            increment: 2.0,
+        };
+        let mut clock = Clock::new(config(control, BonusTime::Share), &time);
+        clock.start(Piece::Black);
+        advance(&time, 4.0);
+        clock.end_turn(Piece::Black);
+        assert_eq!(clock.left(Piece::Black).main, secs(8.0));
+
+        clock.start(Piece::Black);
+        advance(&time, 9.0);
+        assert_eq!(clock.flagged(), Some(Piece::Black));
+        clock.end_turn(Piece::Black);
+        assert_eq!(clock.left(Piece::Black).main, Duration::default());
+    }
+
+    #[test]
+    fn byoyomi_periods_are_used_and_reset() {
+        let time = Cell::new(Duration::default());
+        let control = TimeControl::Byoyomi {
+            main: 5.0,
+            periods: 3,
+            period: 10.0,
+        };
+        let mut clock = Clock::new(config(control, BonusTime::Share), &time);
+        let left = |main: f32, periods: u32, period: f32| TimeLeft {
+            main: secs(main),
+            periods,
+            period: secs(period),
+        };
+        clock.start(Piece::Black);
+        advance(&time, 7.0);
+        assert_eq!(clock.left(Piece::Black), left(0.0, 3, 8.0));
+        // A move within the period gets it back whole.
+        clock.end_turn(Piece::Black);
+        assert_eq!(clock.left(Piece::Black), left(0.0, 3, 10.0));
+
+        clock.start(Piece::Black);
+        advance(&time, 25.0);
+        assert_eq!(clock.left(Piece::Black), left(0.0, 1, 5.0));
+        clock.end_turn(Piece::Black);
+        assert_eq!(clock.left(Piece::Black), left(0.0, 1, 10.0));
+
+        clock.start(Piece::Black);
+        advance(&time, 10.0);
+        assert_eq!(clock.left(Piece::Black), left(0.0, 0, 0.0));
+        assert_eq!(clock.flagged(), Some(Piece::Black));
+    }
+
+    /// Black's main time after a move of 3 seconds completing a five, and a
+    /// bonus turn of 1 second, with 10 seconds and a 2 second increment.
+    fn after_bonus_turn(bonus_time: BonusTime) -> Duration {
+        let time = Cell::new(Duration::default());
+        let control = TimeControl::Fischer {
+            main: 10.0,
+            increment: 2.0,
+        };
+        let mut clock = Clock::new(config(control, bonus_time), &time);
+        clock.start(Piece::Black);
+        advance(&time, 3.0);
+        clock.earn_bonus(Piece::Black);
+        // The five blinks.
+        advance(&time, 2.0);
+        if clock.runs_in_bonus() {
+            clock.start(Piece::Black);
+        }
+        advance(&time, 1.0);
+        clock.end_turn(Piece::Black);
+        clock.left(Piece::Black).main
+    }
+
+    #[test]
+    fn bonus_turn_shares_pauses_or_adds() {
+        assert_eq!(
+            after_bonus_turn(BonusTime::Share),
+            secs(10.0 - 3.0 - 1.0 + 2.0)
+        );
+        assert_eq!(after_bonus_turn(BonusTime::Pause), secs(10.0 - 3.0 + 2.0));
+        assert_eq!(
+            after_bonus_turn(BonusTime::Add),
+            secs(10.0 - 3.0 + 2.0 - 1.0 + 2.0)
+        );
+    }
+
+    #[test]
+    fn config_with_unusable_times_is_refused() {
+        let refused = |control| config(control, BonusTime::Share).validate().is_err();
+        assert!(refused(TimeControl::SuddenDeath { main: -1.0 }));
+        assert!(refused(TimeControl::SuddenDeath { main: f32::NAN }));
+        assert!(refused(TimeControl::Fischer {
+            main: 60.0,
+            increment: -2.0
+        }));
+        assert!(refused(TimeControl::Byoyomi {
+            main: 60.0,
+            periods: 3,
+            period: -10.0
+        }));
+        assert!(refused(TimeControl::Byoyomi {
+            main: 60.0,
+            periods: 3,
+            period: 1e-10
+        }));
+        assert!(!refused(TimeControl::Byoyomi {
+            main: 60.0,
+            periods: 0,
+            period: 0.0
+        }));
+        assert!(!refused(TimeControl::Fischer {
+            main: 0.0,
+            increment: 2.0
+        }));
+    }
+}
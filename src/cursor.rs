@@ -5,13 +5,14 @@ use amethyst::renderer::palette::Srgba;
 use amethyst::renderer::resources::Tint;
 use amethyst::renderer::sprite::SpriteSheetHandle;
 use amethyst::renderer::{SpriteRender, Transparent};
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
 use crate::blink::ToggleHidden;
 use crate::board::PieceRender;
 use crate::{Turn, ARENA_HEIGHT, ARENA_WIDTH};
 
-#[derive(Component, PartialEq, Copy, Clone)]
+#[derive(Component, Debug, PartialEq, Copy, Clone, Deserialize, Serialize)]
 pub struct Coord {
     pub x: i32,
     pub y: i32,
@@ -0,0 +1,22 @@
+use crate::board::Piece;
+use crate::cursor::Coord;
+
+/// Things that happened in the game, published through an
+/// `EventChannel<GameEvent>` for anything that wants to react to them.
+#[derive(Debug, Copy, Clone)]
+pub enum GameEvent {
+    PiecePlaced(Piece, Coord),
+    FiveInARow(Piece, [Coord; 5]),
+    /// The five-in-a-row was taken off the board, and its side plays a bonus
+    /// turn.
+    FiveRemoved(Piece),
+    Captured(Piece, Coord),
+    InvalidClick(Coord),
+    HintShown(Piece, Coord),
+    GameOver,
+    /// The last move was taken back.
+    Undone,
+    NewGame,
+    /// The game was replaced with the network host's.
+    Restored,
+}
@@ -0,0 +1,148 @@
+use amethyst::core::Transform;
+use amethyst::ecs::*;
+use amethyst::renderer::palette::Srgba;
+use amethyst::renderer::resources::Tint;
+use amethyst::renderer::sprite::SpriteSheetHandle;
+use amethyst::renderer::{SpriteRender, Transparent};
+use std::collections::HashMap;
+
+use crate::board::{Board, PieceRender};
+use crate::pattern::ThreatKind;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Highlight {
+    BrokenThree,
+    OpenThree,
+    Four,
+    OpenFour,
+    /// An opponent stone that can be captured in a bonus turn.
+    Capturable,
+    /// A cell where the opponent could complete a five-in-a-row in reply to
+    /// the hovered move.
+    Threat,
+    /// A stone of the five-in-a-row the hovered move would complete.
+    FiveInARow,
+    /// The move suggested by the engine.
+    Hint,
+}
+
+impl Highlight {
+    fn color(&self) -> Srgba {
+        match self {
+            Highlight::BrokenThree => Srgba::new(0.4, 0.8, 1.0, 0.7),
+            Highlight::OpenThree => Srgba::new(1.0, 1.0, 0.3, 0.7),
+            Highlight::Four => Srgba::new(1.0, 0.6, 0.0, 0.9),
+            Highlight::OpenFour => Srgba::new(1.0, 0.0, 1.0, 1.0),
+            Highlight::Capturable => Srgba::new(1.0, 0.4, 0.2, 0.8),
+            Highlight::Threat => Srgba::new(1.0, 0.0, 0.0, 1.0),
+            Highlight::FiveInARow => Srgba::new(0.2, 1.0, 0.3, 1.0),
+            Highlight::Hint => Srgba::new(0.2, 0.5, 1.0, 1.0),
+        }
+    }
+}
+
+impl From<ThreatKind> for Highlight {
+    fn from(kind: ThreatKind) -> Self {
+        match kind {
+            ThreatKind::BrokenThree => Highlight::BrokenThree,
+            ThreatKind::OpenThree => Highlight::OpenThree,
+            ThreatKind::Four => Highlight::Four,
+            ThreatKind::OpenFour => Highlight::OpenFour,
+        }
+    }
+}
+
+/// Cells to draw a colored ring around, grouped by what they mean. Each
+/// feature replaces or clears its own group.
+#[derive(Default)]
+pub struct Highlights(HashMap<Highlight, Vec<usize>>);
+
+impl Highlights {
+    pub fn set<I: IntoIterator<Item = usize>>(&mut self, highlight: Highlight, cells: I) {
+        self.0.insert(highlight, cells.into_iter().collect());
+    }
+    pub fn clear(&mut self, highlight: Highlight) {
+        self.0.remove(&highlight);
+    }
+    /// The highlight shown on each cell; later variants take precedence.
+    fn cells(&self) -> HashMap<usize, Highlight> {
+        let mut cells = HashMap::new();
+        for (highlight, idxs) in self.0.iter() {
+            for idx in idxs {
+                let shown = cells.entry(*idx).or_insert(*highlight);
+                if *highlight > *shown {
+                    *shown = *highlight;
+                }
+            }
+        }
+        cells
+    }
+}
+
+pub struct HighlightSprite(pub SpriteRender);
+
+pub fn initialize_highlights(world: &mut World, sprite_sheet_handle: SpriteSheetHandle) {
+    world.insert(HighlightSprite(SpriteRender::new(sprite_sheet_handle, 0)));
+    world.insert(Highlights::default());
+}
+
+#[derive(Default)]
+pub struct HighlightSystem {
+    rings: HashMap<usize, Entity>,
+}
+
+impl PieceRender for HighlightSystem {}
+
+impl<'a> System<'a> for HighlightSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Transform>,
+        ReadExpect<'a, Board>,
+        ReadExpect<'a, HighlightSprite>,
+        Read<'a, Highlights>,
+        WriteStorage<'a, SpriteRender>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Tint>,
+        WriteStorage<'a, Transparent>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            default_trans,
+            board,
+            sprite,
+            highlights,
+            mut renderers,
+            mut transforms,
+            mut tints,
+            mut transparents,
+        ) = data;
+        let cells = highlights.cells();
+
+        self.rings.retain(|idx, entity| {
+            if cells.contains_key(idx) {
+                return true;
+            }
+            entities.delete(*entity).expect("unable to delete entity");
+            false
+        });
+        for (idx, highlight) in cells {
+            let tint = Tint(highlight.color());
+            if let Some(entity) = self.rings.get(&idx) {
+                tints.insert(*entity, tint).expect("unable to insert tint");
+                continue;
+            }
+            let mut transform = Self::setup_transform(&*default_trans, &board.idx2pos(idx));
+            transform.append_translation_xyz(0.0, 0.0, 0.2);
+            let entity = entities
+                .build_entity()
+                .with(sprite.0.clone(), &mut renderers)
+                .with(transform, &mut transforms)
+                .with(tint, &mut tints)
+                .with(Transparent, &mut transparents)
+                .build();
+            self.rings.insert(idx, entity);
+        }
+    }
+}
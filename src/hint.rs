@@ -0,0 +1,94 @@
+use amethyst::ecs::*;
+use amethyst::shrev::{EventChannel, ReaderId};
+use log::info;
+
+use crate::ai::{AiConfig, Book, SearchHandle, SharedEngine};
+use crate::board::{Board, Piece};
+use crate::event::GameEvent;
+use crate::highlight::{Highlight, Highlights};
+use crate::rules::Position;
+
+/// The hint being computed for the side to move, if any.
+#[derive(Default)]
+pub struct Hint {
+    search: Option<(Piece, SearchHandle)>,
+}
+
+impl Hint {
+    pub fn request(
+        &mut self,
+        position: Position,
+        config: AiConfig,
+        book: &Book,
+        engine: &SharedEngine,
+    ) {
+        if self.search.is_some() {
+            return;
+        }
+        info!("computing a hint for {:?}", position.side());
+        let piece = position.side();
+        self.search = Some((piece, SearchHandle::spawn(position, config, book, engine)));
+    }
+    pub fn cancel(&mut self) {
+        self.search = None;
+    }
+}
+
+/// Shows a finished hint on the board until the next move is made.
+#[derive(Default)]
+pub struct HintSystem {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for HintSystem {
+    type SystemData = (
+        ReadExpect<'a, Board>,
+        Write<'a, Hint>,
+        Write<'a, Highlights>,
+        Write<'a, EventChannel<GameEvent>>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (board, mut hint, mut highlights, mut events): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("hint system is not set up");
+        let moved = events.read(reader).any(|event| {
+            matches!(
+                event,
+                GameEvent::PiecePlaced(..)
+                    | GameEvent::Captured(..)
+                    | GameEvent::FiveInARow(..)
+                    | GameEvent::Undone
+                    | GameEvent::NewGame
+                    | GameEvent::Restored
+            )
+        });
+        if moved {
+            hint.cancel();
+            highlights.clear(Highlight::Hint);
+        }
+
+        let result = match &mut hint.search {
+            Some((piece, search)) => search.poll().map(|result| (*piece, result)),
+            None => None,
+        };
+        if let Some((piece, result)) = result {
+            hint.cancel();
+            if let Some(best) = result.best {
+                info!(
+                    "hint for {:?}: {} (score {}, depth {}, {} nodes)",
+                    piece, best, result.score, result.depth, result.nodes
+                );
+                highlights.set(Highlight::Hint, Some(board.pos2idx(&best)));
+                events.single_write(GameEvent::HintShown(piece, best));
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use amethyst::ecs::*;
+
+use crate::board::Board;
+use crate::record::{Entry, GameRecord};
+use crate::{BonusTurn, GameOver, Turn};
+
+/// The game as it was before a move, to go back to on undo.
+pub struct Snapshot {
+    board: Board,
+    turn: Turn,
+    bonus: bool,
+    entries: usize,
+}
+
+impl Snapshot {
+    pub fn take(world: &World) -> Self {
+        Self {
+            board: (*world.fetch::<Board>()).clone(),
+            turn: *world.fetch::<Turn>(),
+            bonus: world.fetch::<BonusTurn>().0,
+            entries: world.fetch::<GameRecord>().entries.len(),
+        }
+    }
+
+    pub fn turn(&self) -> Turn {
+        self.turn
+    }
+
+    /// Puts the game back, leaving the rendering to follow the `Board`. What
+    /// the players said since stays in the record.
+    pub fn restore(self, world: &World) {
+        *world.fetch_mut::<Board>() = self.board;
+        *world.fetch_mut::<Turn>() = self.turn;
+        world.fetch_mut::<BonusTurn>().0 = self.bonus;
+        world.fetch_mut::<GameOver>().0 = false;
+        let mut record = world.fetch_mut::<GameRecord>();
+        let len = self.entries.min(record.entries.len());
+        let later = record.entries.split_off(len);
+        record
+            .entries
+            .extend(later.into_iter().filter(Entry::is_talk));
+    }
+}
+
+/// Snapshots taken before each move, oldest first.
+#[derive(Default)]
+pub struct History(pub Vec<Snapshot>);
@@ -0,0 +1,109 @@
+use amethyst::ecs::*;
+use amethyst::ui::{Anchor, LineMode, UiText, UiTransform};
+
+use crate::board::Piece;
+use crate::clock::Clock;
+use crate::marker::Markers;
+
+const STATUS_FONT_SIZE: f32 = 14.0;
+const CLOCK_FONT_SIZE: f32 = 16.0;
+const CLOCK_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const LOW_TIME_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+
+/// A line shown at the top of the window, such as what the engine is
+/// thinking about. Empty when there is nothing to tell.
+#[derive(Default)]
+pub struct Status(pub String);
+
+pub struct Hud {
+    status: Entity,
+    /// Each side's clock, indexed by `Piece::idx`.
+    clocks: [Entity; 2],
+}
+
+/// Must run after `initialize_markers`, whose font it borrows.
+pub fn initialize_hud(world: &mut World) {
+    let font = world.fetch::<Markers>().font.clone();
+    let ui_transform = UiTransform::new(
+        "status".to_string(),
+        Anchor::TopMiddle,
+        Anchor::TopMiddle,
+        0.0,
+        -0.01,
+        2.0,
+        0.98,
+        0.04,
+    )
+    .into_percent()
+    .into_transparent();
+    let ui_text = UiText::new(
+        font.clone(),
+        String::new(),
+        [1.0, 1.0, 1.0, 1.0],
+        STATUS_FONT_SIZE,
+        LineMode::Single,
+        Anchor::Middle,
+    );
+    let status = world
+        .create_entity()
+        .with(ui_transform)
+        .with(ui_text)
+        .build();
+    let mut clock = |id: &str, anchor: Anchor| {
+        let ui_transform =
+            UiTransform::new(id.to_string(), anchor, anchor, 0.0, -0.05, 2.0, 0.3, 0.04)
+                .into_percent()
+                .into_transparent();
+        let ui_text = UiText::new(
+            font.clone(),
+            String::new(),
+            CLOCK_COLOR,
+            CLOCK_FONT_SIZE,
+            LineMode::Single,
+            anchor,
+        );
+        world
+            .create_entity()
+            .with(ui_transform)
+            .with(ui_text)
+            .build()
+    };
+    let clocks = [
+        clock("black clock", Anchor::TopLeft),
+        clock("white clock", Anchor::TopRight),
+    ];
+    world.insert(Hud { status, clocks });
+    world.insert(Status::default());
+}
+
+pub struct HudSystem;
+
+impl<'a> System<'a> for HudSystem {
+    type SystemData = (
+        ReadExpect<'a, Hud>,
+        Read<'a, Status>,
+        ReadExpect<'a, Clock>,
+        WriteStorage<'a, UiText>,
+    );
+
+    fn run(&mut self, (hud, status, clock, mut ui_texts): Self::SystemData) {
+        if let Some(ui_text) = ui_texts.get_mut(hud.status) {
+            if ui_text.text != status.0 {
+                ui_text.text = status.0.clone();
+            }
+        }
+        for side in [Piece::Black, Piece::White].iter() {
+            if let Some(ui_text) = ui_texts.get_mut(hud.clocks[side.idx()]) {
+                let text = clock.text(*side);
+                if ui_text.text != text {
+                    ui_text.text = text;
+                }
+                ui_text.color = if clock.is_low(*side) {
+                    LOW_TIME_COLOR
+                } else {
+                    CLOCK_COLOR
+                };
+            }
+        }
+    }
+}
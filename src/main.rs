@@ -1,26 +1,77 @@
 use amethyst::assets::{AssetStorage, Loader};
+use amethyst::audio::{AudioBundle, DjSystemDesc};
+use amethyst::config::Config;
 use amethyst::core::{Hidden, Transform, TransformBundle};
 use amethyst::ecs::*;
+use amethyst::input::{InputBundle, StringBindings};
 use amethyst::renderer::sprite::SpriteSheetHandle;
 use amethyst::renderer::types::DefaultBackend;
 use amethyst::renderer::{
     Camera, ImageFormat, RenderFlat2D, RenderToWindow, RenderingBundle, SpriteSheet,
     SpriteSheetFormat, Texture,
 };
-use amethyst::winit::{ElementState, Event, MouseButton, WindowEvent};
+use amethyst::shrev::EventChannel;
+use amethyst::ui::{RenderUi, UiBundle};
+use amethyst::winit::{
+    ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent,
+};
 use amethyst::{
-    Application, GameData, GameDataBuilder, SimpleState, SimpleTrans, StateData, StateEvent, Trans,
+    Application, GameData, GameDataBuilder, LoggerConfig, SimpleState, SimpleTrans, StateData,
+    StateEvent, StdoutLog, Trans,
 };
-use log::info;
-use std::time::Duration;
+use log::{error, info, warn};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+mod ai;
+mod anim;
 mod blink;
 mod board;
+mod chat;
+mod cli;
+mod clock;
 mod cursor;
+mod event;
+mod highlight;
+mod hint;
+mod history;
+mod hud;
+mod marker;
+mod net;
+mod notation;
+mod offer;
+mod pattern;
+mod preview;
+mod record;
+mod rules;
+mod selfplay;
+mod sound;
+mod sync;
+mod tactics;
 
+use ai::{AiConfig, Book, SearchHandle, SharedEngine};
+use anim::AnimationConfig;
 use blink::{PiecesBlinkState, ToggleHidden};
-use board::{initialize_board, Board, Piece, WantsToPlacePiece};
+use board::{initialize_board, Board, Piece, WantsToPlacePiece, BOARD_HALF_WIDTH};
+use chat::{initialize_chat, Chat, Emote};
+use clock::{Clock, ClockConfig};
 use cursor::{initialize_cursor, Coord, Cursor};
+use event::GameEvent;
+use highlight::initialize_highlights;
+use hint::Hint;
+use history::{History, Snapshot};
+use hud::{initialize_hud, Status};
+use marker::{initialize_markers, ShowMoveNumbers};
+use net::{
+    default_name, resolve, Api, GameState, Message, NetConfig, NetEvent, Request, Session, Update,
+    LOOPBACK, PROTOCOL_VERSION,
+};
+use offer::{Offer, Prompt};
+use preview::ShowThreats;
+use record::{Entry, GameRecord};
+use rules::Position;
+use sound::{initialize_audio, AudioConfig, Music};
+use sync::PieceEntities;
 
 pub const ARENA_HEIGHT: f32 = 800.0;
 pub const ARENA_WIDTH: f32 = 800.0;
@@ -29,8 +80,10 @@ const BOARD_TEXTURE: &str = "texture/board.png";
 const BOARD_SPRITE_SHEET: &str = "texture/board.ron";
 const PIECE_TEXTURE: &str = "texture/piece.png";
 const PIECE_SPRITE_SHEET: &str = "texture/piece.ron";
+const MARKER_TEXTURE: &str = "texture/marker.png";
+const MARKER_SPRITE_SHEET: &str = "texture/marker.ron";
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Turn {
     Player,
     Computer,
@@ -38,6 +91,8 @@ pub enum Turn {
 
 pub struct BonusTurn(pub bool);
 
+pub struct GameOver(pub bool);
+
 impl Turn {
     pub fn piece(&self) -> Piece {
         match self {
@@ -58,23 +113,44 @@ struct LogicalSize {
     height: f64,
 }
 
-struct State;
+struct State {
+    /// The engine's search for its next move, when it plays `Turn::Computer`.
+    computer: Option<SearchHandle>,
+    /// The game hosted or joined over the network, if any.
+    session: Option<Session>,
+    /// A question for a player on this machine, waiting for Y or N.
+    prompt: Option<Prompt>,
+    /// Our offer, waiting for the other end's answer.
+    offered: Option<Offer>,
+    /// The score of the engine's last move, for its own side.
+    engine_score: Option<i32>,
+    /// Where S exports the game record.
+    records_dir: PathBuf,
+}
 
 impl ToggleHidden for State {}
 
 impl State {
-    pub fn new() -> Self {
-        Self
+    pub fn new(session: Option<Session>, records_dir: PathBuf) -> Self {
+        Self {
+            computer: None,
+            session,
+            prompt: None,
+            offered: None,
+            engine_score: None,
+            records_dir,
+        }
     }
     fn cursor_moved_bonus_turn(&self, world: &World, old_coord: &Coord, coord: &Coord) {
         let board = world.fetch::<Board>();
+        let rendered = world.fetch::<PieceEntities>();
         let mut hiddens = world.write_storage::<Hidden>();
-        if let Some(entity) = board.get_entity(old_coord) {
+        if let Some(entity) = rendered.get(board.pos2idx(old_coord)) {
             Self::toggle_hidden(&mut hiddens, true, entity);
         }
         let piece = world.fetch_mut::<Turn>().piece();
         if board.get_piece(coord) != Some(&piece) {
-            if let Some(entity) = board.get_entity(coord) {
+            if let Some(entity) = rendered.get(board.pos2idx(coord)) {
                 Self::toggle_hidden(&mut hiddens, false, entity);
             }
         }
@@ -93,37 +169,410 @@ impl State {
         if coord != *old_coord {
             let cursor = cursor.get_mut(cursor_entity).unwrap();
             cursor.set_show(!coord.out_of_bound);
-            if world.fetch::<BonusTurn>().0 {
+            if world.fetch::<BonusTurn>().0 && !computer_to_move(world) {
                 self.cursor_moved_bonus_turn(world, old_coord, &coord);
             }
         }
         *old_coord = coord;
     }
 
-    fn mouse_clicked_bonus_turn(&self, world: &mut World, pos: &Coord) {
-        let entity_to_remove = {
-            let mut board = world.fetch_mut::<Board>();
-            let piece = world.fetch_mut::<Turn>().piece();
-            let mut entity = None;
-            if board.get_piece(pos) != Some(&piece) && board.remove_piece(pos).is_some() {
-                entity = board.remove_entity(pos);
-            }
-            entity
+    fn key_pressed(&mut self, world: &mut World, key: VirtualKeyCode) {
+        if let Some(prompt) = self.prompt {
+            match key {
+                VirtualKeyCode::Y => return self.prompt_answered(world, prompt, true),
+                VirtualKeyCode::N => return self.prompt_answered(world, prompt, false),
+                _ => {}
+            }
+        }
+        match key {
+            VirtualKeyCode::N => {
+                let mut show_numbers = world.fetch_mut::<ShowMoveNumbers>();
+                show_numbers.0 = !show_numbers.0;
+            }
+            VirtualKeyCode::T => {
+                let mut show_threats = world.fetch_mut::<ShowThreats>();
+                show_threats.0 = !show_threats.0;
+            }
+            VirtualKeyCode::H => self.request_hint(world),
+            VirtualKeyCode::U => self.offer(world, Offer::Takeback),
+            VirtualKeyCode::D => self.offer(world, Offer::Draw),
+            VirtualKeyCode::S => self.export_record(world),
+            VirtualKeyCode::R => {
+                if let Some(side) = self.asking_side(world, Offer::Draw) {
+                    self.ask(world, Prompt::Resign(side));
+                }
+            }
+            VirtualKeyCode::F2 => self.new_game(world),
+            VirtualKeyCode::Return if self.local_player().is_some() => {
+                world.fetch_mut::<Chat>().input = Some(String::new());
+            }
+            VirtualKeyCode::M => {
+                let mut chat = world.fetch_mut::<Chat>();
+                chat.muted = !chat.muted;
+            }
+            VirtualKeyCode::Key1 => self.emote(world, 1),
+            VirtualKeyCode::Key2 => self.emote(world, 2),
+            VirtualKeyCode::Key3 => self.emote(world, 3),
+            VirtualKeyCode::Key4 => self.emote(world, 4),
+            _ => {}
+        }
+    }
+
+    /// Writes the game record in every format, named after the time.
+    fn export_record(&self, world: &World) {
+        if let Err(err) = std::fs::create_dir_all(&self.records_dir) {
+            error!("unable to create {:?}: {}", self.records_dir, err);
+            return;
+        }
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let record = world.fetch::<GameRecord>();
+        for ext in ["ron", "psq", "txt"].iter() {
+            let path = self.records_dir.join(format!("game-{}.{}", secs, ext));
+            match notation::save(&record, &path) {
+                Ok(()) => info!("game record written to {:?}", path),
+                Err(err) => error!("unable to write {:?}: {}", path, err),
+            }
+        }
+    }
+
+    /// Edits the chat message being typed, and sends it on return.
+    fn chat_key_pressed(&mut self, world: &mut World, key: VirtualKeyCode) {
+        let mut chat = world.fetch_mut::<Chat>();
+        match key {
+            VirtualKeyCode::Return => {
+                let text = chat.input.take().unwrap_or_default();
+                let text = chat::truncate(text.trim());
+                drop(chat);
+                if !text.is_empty() {
+                    self.say(world, |side| Message::Chat { side, text });
+                }
+            }
+            VirtualKeyCode::Escape => chat.input = None,
+            VirtualKeyCode::Back => {
+                if let Some(input) = &mut chat.input {
+                    input.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn emote(&mut self, world: &mut World, number: usize) {
+        if let Some(emote) = Emote::from_number(number) {
+            self.say(world, |side| Message::Emote { side, emote });
+        }
+    }
+
+    /// The side played on this machine in a networked game, unless
+    /// watching.
+    fn local_player(&self) -> Option<Piece> {
+        self.session.as_ref().and_then(|session| session.local)
+    }
+
+    /// Shows, records and sends a chat message or emote of ours.
+    fn say<F: FnOnce(Piece) -> Message>(&mut self, world: &mut World, message: F) {
+        let message = match self.local_player() {
+            Some(side) => message(side),
+            None => return,
         };
-        if let Some(entity) = entity_to_remove {
-            world
-                .delete_entity(entity)
-                .expect("unable to delete entity");
+        said(world, &message, true);
+        self.tell(message);
+    }
+
+    /// Sends to the other end: from a host to everyone, from a guest to the
+    /// host.
+    fn tell(&mut self, message: Message) {
+        match &self.session {
+            Some(session) if session.is_host() => self.broadcast(message),
+            _ => self.send(message),
+        }
+    }
+
+    fn request_hint(&self, world: &mut World) {
+        if world.fetch::<GameOver>().0 || computer_to_move(world) {
+            return;
+        }
+        let position = position(world);
+        info!(
+            "hint requested, {} used so far",
+            world.fetch::<GameRecord>().hints(position.side())
+        );
+        let config = (*world.fetch::<AiConfig>()).clone();
+        let book = world.fetch::<Book>();
+        let engine = world.fetch::<SharedEngine>();
+        world
+            .fetch_mut::<Hint>()
+            .request(position, config, &book, &engine);
+    }
+
+    /// The side on this machine asking for `offer`, or resigning as for a
+    /// draw: the side to move in hot-seat play, but for a takeback the side
+    /// that moved last.
+    fn asking_side(&self, world: &World, offer: Offer) -> Option<Piece> {
+        if self.session.is_some() {
+            return self.local_player();
+        }
+        if world.fetch::<AiConfig>().opponent {
+            return Some(Turn::Player.piece());
+        }
+        match offer {
+            Offer::Draw => Some(world.fetch::<Turn>().piece()),
+            Offer::Takeback => world
+                .fetch::<History>()
+                .0
+                .last()
+                .map(|last| last.turn().piece()),
         }
     }
-    fn mouse_clicked(&self, world: &mut World, pos: Coord) {
+
+    fn ask(&mut self, world: &mut World, prompt: Prompt) {
+        if world.fetch::<GameOver>().0 || self.prompt.is_some() || self.offered.is_some() {
+            info!("not now: {}", prompt);
+            return;
+        }
+        world.fetch_mut::<Status>().0 = prompt.to_string();
+        self.prompt = Some(prompt);
+    }
+
+    /// Asks the other side for a draw or a takeback: the engine answers at
+    /// once, a hot-seat player with Y or N, and a remote one over the network.
+    fn offer(&mut self, world: &mut World, offer: Offer) {
+        let side = match self.asking_side(world, offer) {
+            Some(side) => side,
+            None => return,
+        };
+        if world.fetch::<GameOver>().0 || self.prompt.is_some() || self.offered.is_some() {
+            info!("no offer now");
+            return;
+        }
+        if offer == Offer::Takeback && !self.has_moved(world, side) {
+            info!("{:?} has no move to take back", side);
+            return;
+        }
+        info!("{:?} asks for {}", side, offer);
+        world
+            .fetch_mut::<GameRecord>()
+            .entries
+            .push(Entry::Offered(side, offer));
+        if self.session.is_some() {
+            self.offered = Some(offer);
+            world.fetch_mut::<Status>().0 = format!("asked for {}, waiting for an answer", offer);
+            self.tell(Message::Offer { side, offer });
+        } else if world.fetch::<AiConfig>().opponent {
+            let accepted = match offer {
+                Offer::Draw => {
+                    let draw_score = world.fetch::<AiConfig>().draw_score;
+                    self.engine_score.map_or(false, |score| score <= draw_score)
+                }
+                Offer::Takeback => world.fetch::<AiConfig>().grants_takebacks,
+            };
+            self.answered(world, side.next(), offer, accepted);
+        } else {
+            self.ask(world, Prompt::Offer(side, offer));
+        }
+    }
+
+    fn prompt_answered(&mut self, world: &mut World, prompt: Prompt, yes: bool) {
+        self.prompt = None;
+        world.fetch_mut::<Status>().0.clear();
+        match prompt {
+            Prompt::Resign(side) if yes => {
+                resigned(world, side);
+                self.tell(Message::Resigned { side });
+            }
+            Prompt::Resign(_) => {}
+            Prompt::Offer(side, offer) => {
+                let answer = Message::Answer {
+                    side: side.next(),
+                    offer,
+                    accepted: yes,
+                };
+                self.tell(answer);
+                self.answered(world, side.next(), offer, yes);
+            }
+        }
+    }
+
+    /// Carries out `side`'s answer to the other side's offer, and records
+    /// it. A guest leaves taking moves back to the host.
+    fn answered(&mut self, world: &mut World, side: Piece, offer: Offer, accepted: bool) {
+        let verb = if accepted { "accepts" } else { "declines" };
+        info!("{:?} {} {}", side, verb, offer);
+        let guest = self
+            .session
+            .as_ref()
+            .map_or(false, |session| !session.is_host());
+        if accepted && offer == Offer::Takeback && !guest {
+            self.take_back(world, side.next());
+            self.broadcast(Message::Sync(GameState::take(world)));
+        }
+        world
+            .fetch_mut::<GameRecord>()
+            .entries
+            .push(Entry::Answered(side, offer, accepted));
+        let status = format!("{:?} {} {}", side, verb, offer);
+        if accepted && offer == Offer::Draw {
+            end_game(world, status);
+        } else {
+            world.fetch_mut::<Status>().0 = status;
+        }
+    }
+
+    /// Whether `side` played a move that can be taken back.
+    fn has_moved(&self, world: &World, side: Piece) -> bool {
+        world
+            .fetch::<History>()
+            .0
+            .iter()
+            .any(|snapshot| snapshot.turn().piece() == side)
+    }
+
+    /// Takes back the last move of `side`, and every move after it.
+    fn take_back(&mut self, world: &mut World, side: Piece) {
+        self.stop_computer(world);
+        let mut snapshot = None;
+        {
+            let mut history = world.fetch_mut::<History>();
+            while let Some(last) = history.0.pop() {
+                let ours = last.turn().piece() == side;
+                snapshot = Some(last);
+                if ours {
+                    break;
+                }
+            }
+        }
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        info!("taking back to {:?}'s move", snapshot.turn());
+        snapshot.restore(world);
+        world.write_storage::<WantsToPlacePiece>().clear();
+        world
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .single_write(GameEvent::Undone);
+    }
+
+    fn new_game(&mut self, world: &mut World) {
+        if self.session.is_some() {
+            info!("no new game in a networked game");
+            return;
+        }
+        info!("new game");
+        self.stop_computer(world);
+        self.prompt = None;
+        self.engine_score = None;
+        *world.fetch_mut::<Board>() = Board::new(BOARD_HALF_WIDTH);
+        *world.fetch_mut::<Turn>() = Turn::Player;
+        world.fetch_mut::<BonusTurn>().0 = false;
+        world.fetch_mut::<GameOver>().0 = false;
+        *world.fetch_mut::<GameRecord>() = GameRecord::default();
+        world.fetch_mut::<History>().0.clear();
+        world.write_storage::<WantsToPlacePiece>().clear();
+        world
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .single_write(GameEvent::NewGame);
+    }
+
+    /// Starts the engine's search when it is its turn, shows how far it has
+    /// got, and plays the move once it is done.
+    fn play_computer(&mut self, world: &mut World) {
+        if !computer_to_move(world) {
+            self.stop_computer(world);
+            return;
+        }
+        let search = match &mut self.computer {
+            Some(search) => search,
+            None => {
+                info!("computer is thinking");
+                let config = (*world.fetch::<AiConfig>()).clone();
+                let search = SearchHandle::spawn(
+                    position(world),
+                    config,
+                    &world.fetch::<Book>(),
+                    &world.fetch::<SharedEngine>(),
+                );
+                self.computer = Some(search);
+                world.fetch_mut::<Status>().0 = "thinking...".to_string();
+                return;
+            }
+        };
+        let result = match search.poll() {
+            Some(result) => result,
+            None => {
+                if let Some(progress) = search.progress() {
+                    let line: Vec<_> = progress.pv.iter().map(|pos| pos.to_string()).collect();
+                    world.fetch_mut::<Status>().0 = format!(
+                        "thinking: depth {}, {} nodes, score {}, line {}",
+                        progress.depth,
+                        progress.nodes,
+                        progress.score,
+                        line.join(" ")
+                    );
+                }
+                return;
+            }
+        };
+        self.stop_computer(world);
+        info!(
+            "computer plays {:?} (score {}, depth {}, {} nodes)",
+            result.best, result.score, result.depth, result.nodes
+        );
+        self.engine_score = Some(result.score);
+        if let Some(best) = result.best {
+            self.play_move(world, best);
+        }
+    }
+
+    fn stop_computer(&mut self, world: &mut World) {
+        if self.computer.take().is_some() {
+            world.fetch_mut::<Status>().0.clear();
+        }
+    }
+
+    fn mouse_clicked_bonus_turn(&self, world: &mut World, pos: &Coord) {
+        let mut board = world.fetch_mut::<Board>();
+        let piece = world.fetch_mut::<Turn>().piece();
+        if board.get_piece(pos) != Some(&piece) {
+            if let Some(captured) = board.remove_piece(pos) {
+                world
+                    .fetch_mut::<EventChannel<GameEvent>>()
+                    .single_write(GameEvent::Captured(captured, *pos));
+            }
+        }
+    }
+    fn mouse_clicked(&mut self, world: &mut World, pos: Coord) {
+        if computer_to_move(world) || self.remote_to_move(world) {
+            return;
+        }
+        match &mut self.session {
+            // The move is played once the host accepts it.
+            Some(session) if !session.is_host() => {
+                if check_move(world, &pos) {
+                    session.send(Message::Move { pos });
+                }
+            }
+            _ => {
+                if self.play_move(world, pos) {
+                    self.broadcast(Message::Moved { pos });
+                }
+            }
+        }
+    }
+
+    /// Plays a legal move for the side to move, and tells whether it did.
+    fn play_move(&self, world: &mut World, pos: Coord) -> bool {
+        if !check_move(world, &pos) {
+            return false;
+        }
+        let snapshot = Snapshot::take(world);
+        world.fetch_mut::<History>().0.push(snapshot);
         info!("bonus turn: {}", world.fetch::<BonusTurn>().0);
         if world.fetch::<BonusTurn>().0 {
             self.mouse_clicked_bonus_turn(world, &pos);
         }
-        if world.fetch::<Board>().get_piece(&pos).is_some() {
-            return;
-        }
         world.fetch_mut::<BonusTurn>().0 = false;
         let piece = {
             let mut turn = world.fetch_mut::<Turn>();
@@ -137,6 +586,313 @@ impl State {
         piece_storage
             .insert(cursor_entity, WantsToPlacePiece { piece, pos })
             .expect("unable to insert component");
+        true
+    }
+
+    fn remote_to_move(&self, world: &World) -> bool {
+        self.session.as_ref().map_or(false, |session| {
+            Some(world.fetch::<Turn>().piece()) != session.local
+        })
+    }
+
+    fn send(&mut self, message: Message) {
+        if let Some(session) = &mut self.session {
+            session.send(message);
+        }
+    }
+
+    fn broadcast(&mut self, message: Message) {
+        if let Some(session) = &mut self.session {
+            session.broadcast(message);
+        }
+    }
+
+    /// Answers the requests of API clients.
+    fn api_update(&mut self, world: &mut World) {
+        let requests = match world.try_fetch_mut::<Api>() {
+            Some(mut api) => api.poll(),
+            None => return,
+        };
+        for (id, request) in requests {
+            let answer = match request {
+                Request::State => Some(Update::state(
+                    &world.fetch::<Board>(),
+                    *world.fetch::<Turn>(),
+                    world.fetch::<BonusTurn>().0,
+                    world.fetch::<GameOver>().0,
+                )),
+                Request::Place { x, y } => self.api_move(world, Coord::new_bounded(x, y), false),
+                Request::Capture { x, y } => self.api_move(world, Coord::new_bounded(x, y), true),
+            };
+            if let Some(answer) = answer {
+                world.fetch::<Api>().send(id, &answer);
+            }
+        }
+    }
+
+    /// Plays an API client's move as if it were clicked, or tells why not.
+    fn api_move(&mut self, world: &mut World, pos: Coord, capture: bool) -> Option<Update> {
+        let checked = if computer_to_move(world) || self.remote_to_move(world) {
+            Err("it is not this side's turn to play".to_string())
+        } else {
+            position(world).check(&pos).map_err(|err| err.to_string())
+        };
+        let reason = match checked {
+            Ok(captured) if captured.is_some() == capture => {
+                self.mouse_clicked(world, pos);
+                return None;
+            }
+            Ok(Some(_)) => "the move is a capture".to_string(),
+            Ok(None) => "there is nothing to capture".to_string(),
+            Err(reason) => reason,
+        };
+        info!("rejected the API move at {}: {}", pos, reason);
+        Some(Update::Rejected { reason })
+    }
+
+    fn network_update(&mut self, world: &mut World) {
+        let events = match &mut self.session {
+            Some(session) => session.poll(|| GameState::take(world)),
+            None => return,
+        };
+        for event in events {
+            match event {
+                NetEvent::Connected(addr) => {
+                    world.fetch_mut::<Status>().0 = format!("{} joined", addr);
+                }
+                NetEvent::Watching(addr) => {
+                    world.fetch_mut::<Status>().0 = format!("{} is watching", addr);
+                }
+                NetEvent::Disconnected => {
+                    world.fetch_mut::<Status>().0 = "the opponent left".to_string();
+                }
+                NetEvent::Away => {
+                    world.fetch_mut::<Status>().0 = "lost the connection, waiting".to_string();
+                    if let Some(side) = self.remote_side() {
+                        self.broadcast(Message::Away { side });
+                    }
+                }
+                NetEvent::Back(addr) => {
+                    world.fetch_mut::<Status>().0 = format!("{} is back", addr);
+                    if let Some(side) = self.remote_side() {
+                        self.broadcast(Message::Back { side });
+                    }
+                }
+                NetEvent::Forfeited => {
+                    if let Some(side) = self.remote_side() {
+                        self.broadcast(Message::Forfeited { side });
+                        forfeit(world, side);
+                    }
+                }
+                NetEvent::Message(message) => self.message_received(world, message),
+            }
+        }
+    }
+
+    fn message_received(&mut self, world: &mut World, message: Message) {
+        let host = match &self.session {
+            Some(session) => session.is_host(),
+            None => return,
+        };
+        match message {
+            Message::Welcome {
+                version,
+                side,
+                token,
+            } if !host => {
+                if version != PROTOCOL_VERSION {
+                    error!(
+                        "the host speaks protocol version {}, not {}",
+                        version, PROTOCOL_VERSION
+                    );
+                    self.disconnect();
+                    return;
+                }
+                let status = match side {
+                    Some(side) => format!("playing {:?}", side),
+                    None => "watching".to_string(),
+                };
+                info!("{}", status);
+                if let Some(session) = &mut self.session {
+                    session.welcomed(side, token);
+                }
+                world.fetch_mut::<Status>().0 = status;
+            }
+            Message::Move { pos } if host => {
+                let legal = match &mut self.session {
+                    Some(session) => session.check_move(
+                        &position(world),
+                        world.fetch::<GameOver>().0,
+                        &pos,
+                        || GameState::take(world),
+                    ),
+                    None => false,
+                };
+                if legal && self.play_move(world, pos) {
+                    self.broadcast(Message::Moved { pos });
+                }
+            }
+            Message::Moved { pos } if !host => {
+                if !self.play_move(world, pos) {
+                    error!("out of step with the host at {}", pos);
+                    self.send(Message::Resync);
+                }
+            }
+            Message::Rejected { reason } => {
+                info!("rejected: {}", reason);
+                world.fetch_mut::<Status>().0 = format!("rejected: {}", reason);
+            }
+            Message::Resync if host => self.send(Message::Sync(GameState::take(world))),
+            Message::Sync(state) if !host => {
+                // Most syncs follow a rejected move, and change nothing.
+                if state == GameState::take(world) {
+                    return;
+                }
+                info!("synced with the host");
+                state.restore(world);
+                if state.over {
+                    world.fetch_mut::<Status>().0 = "the game is over".to_string();
+                }
+                world.write_storage::<WantsToPlacePiece>().clear();
+                world
+                    .fetch_mut::<EventChannel<GameEvent>>()
+                    .single_write(GameEvent::Restored);
+            }
+            Message::Away { side } if !host => {
+                world.fetch_mut::<Status>().0 = format!("{:?} lost the connection", side);
+            }
+            Message::Back { side } if !host => {
+                world.fetch_mut::<Status>().0 = format!("{:?} is back", side);
+            }
+            Message::Forfeited { side } if !host => forfeit(world, side),
+            Message::Chat { side, text } => {
+                let side = if host { self.remote_side() } else { Some(side) };
+                if let Some(side) = side {
+                    let text = chat::truncate(&text);
+                    self.heard(world, Message::Chat { side, text });
+                }
+            }
+            Message::Emote { side, emote } => {
+                let side = if host { self.remote_side() } else { Some(side) };
+                if let Some(side) = side {
+                    self.heard(world, Message::Emote { side, emote });
+                }
+            }
+            Message::Offer { side, offer } => {
+                let side = if host { self.remote_side() } else { Some(side) };
+                if let Some(side) = side {
+                    self.offer_received(world, side, offer);
+                }
+            }
+            Message::Answer {
+                side,
+                offer,
+                accepted,
+            } => {
+                let side = if host { self.remote_side() } else { Some(side) };
+                if let Some(side) = side {
+                    self.answer_received(world, side, offer, accepted);
+                }
+            }
+            Message::OutOfTime { side } if !host => out_of_time(world, side),
+            Message::Resigned { side } => {
+                let side = if host { self.remote_side() } else { Some(side) };
+                if let Some(side) = side {
+                    resigned(world, side);
+                    self.tell_spectators(Message::Resigned { side });
+                }
+            }
+            message => error!("unexpected {:?}", message),
+        }
+    }
+
+    /// Shows and records a chat message or emote from the other end, which
+    /// a host passes on to the spectators.
+    fn heard(&mut self, world: &mut World, message: Message) {
+        said(world, &message, false);
+        self.tell_spectators(message);
+    }
+
+    /// Passes a message from the guest on to the spectators, on a host.
+    fn tell_spectators(&mut self, message: Message) {
+        if let Some(session) = &mut self.session {
+            if session.is_host() {
+                session.tell_spectators(message);
+            }
+        }
+    }
+
+    /// Ends the game once a clock runs out. In a networked game, the host's
+    /// clock has the final say.
+    fn check_clock(&mut self, world: &mut World) {
+        let guest = self
+            .session
+            .as_ref()
+            .map_or(false, |session| !session.is_host());
+        if guest || world.fetch::<GameOver>().0 {
+            return;
+        }
+        let flagged = world.fetch::<Clock>().flagged();
+        if let Some(side) = flagged {
+            self.stop_computer(world);
+            out_of_time(world, side);
+            self.tell(Message::OutOfTime { side });
+        }
+    }
+
+    /// Asks the player on this machine to answer the other end's offer. A
+    /// spectator only records it.
+    fn offer_received(&mut self, world: &mut World, side: Piece, offer: Offer) {
+        info!("{:?} asks for {}", side, offer);
+        world
+            .fetch_mut::<GameRecord>()
+            .entries
+            .push(Entry::Offered(side, offer));
+        self.tell_spectators(Message::Offer { side, offer });
+        if self.local_player() != Some(side.next()) {
+            return;
+        }
+        if world.fetch::<GameOver>().0 || self.prompt.is_some() || self.offered.is_some() {
+            // Nobody is there to answer it now.
+            self.tell(Message::Answer {
+                side: side.next(),
+                offer,
+                accepted: false,
+            });
+            self.answered(world, side.next(), offer, false);
+            return;
+        }
+        self.ask(world, Prompt::Offer(side, offer));
+    }
+
+    fn answer_received(&mut self, world: &mut World, side: Piece, offer: Offer, accepted: bool) {
+        if self.local_player() == Some(side.next()) {
+            if self.offered != Some(offer) {
+                warn!("{:?} answered {}, which was not asked for", side, offer);
+                return;
+            }
+            self.offered = None;
+        }
+        self.tell_spectators(Message::Answer {
+            side,
+            offer,
+            accepted,
+        });
+        self.answered(world, side, offer, accepted);
+    }
+
+    /// The side played over the network, on a host only.
+    fn remote_side(&self) -> Option<Piece> {
+        self.session
+            .as_ref()
+            .filter(|session| session.is_host())
+            .and_then(Session::remote)
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(session) = &mut self.session {
+            session.disconnect();
+        }
     }
 }
 
@@ -145,23 +901,54 @@ impl SimpleState for State {
         let world = data.world;
         let board_handle = load_sprite_sheet(world, BOARD_TEXTURE, BOARD_SPRITE_SHEET);
         let piece_handle = load_sprite_sheet(world, PIECE_TEXTURE, PIECE_SPRITE_SHEET);
+        let marker_handle = load_sprite_sheet(world, MARKER_TEXTURE, MARKER_SPRITE_SHEET);
         initialize_board(world, board_handle);
-        initialize_cursor(world, piece_handle);
+        initialize_cursor(world, piece_handle.clone());
+        initialize_markers(world, piece_handle);
+        initialize_hud(world);
+        initialize_chat(world);
+        initialize_highlights(world, marker_handle);
         initialize_camara(world);
+        initialize_audio(world);
         world.insert(Turn::Player);
         world.insert(BonusTurn(false));
+        world.insert(GameOver(false));
+        world.insert(History::default());
         world.insert(LogicalSize {
             width: ARENA_WIDTH as f64,
             height: ARENA_HEIGHT as f64,
         });
+        if let Some(addr) = self.session.as_ref().and_then(Session::local_addr) {
+            world.fetch_mut::<Status>().0 = format!("waiting for a guest at {}", addr);
+        }
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.stop_computer(data.world);
+        data.world.fetch_mut::<Hint>().cancel();
+        self.disconnect();
     }
 
     fn fixed_update(&mut self, data: StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
-        let mut board = data.world.fetch_mut::<Board>();
-        if let Some((fir, turn)) = board.take_five_in_a_row() {
-            let time = Duration::from_secs(2);
-            return Trans::Push(Box::new(PiecesBlinkState::new(fir, time, turn)));
+        {
+            let mut board = data.world.fetch_mut::<Board>();
+            if let Some((fir, turn)) = board.take_five_in_a_row() {
+                let time = Duration::from_secs(2);
+                return Trans::Push(Box::new(PiecesBlinkState::new(fir, time, turn)));
+            }
+            let mut game_over = data.world.fetch_mut::<GameOver>();
+            if board.is_full() && !game_over.0 {
+                info!("game over");
+                game_over.0 = true;
+                data.world
+                    .fetch_mut::<EventChannel<GameEvent>>()
+                    .single_write(GameEvent::GameOver);
+            }
         }
+        self.check_clock(data.world);
+        self.network_update(data.world);
+        self.api_update(data.world);
+        self.play_computer(data.world);
         Trans::None
     }
 
@@ -195,6 +982,29 @@ impl SimpleState for State {
                     WindowEvent::CursorMoved { position, .. } => {
                         self.cursor_moved(world, position.x, position.y);
                     }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => {
+                        if world.fetch::<Chat>().input.is_some() {
+                            self.chat_key_pressed(world, key);
+                        } else {
+                            self.key_pressed(world, key);
+                        }
+                    }
+                    WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                        let mut chat = world.fetch_mut::<Chat>();
+                        if let Some(input) = &mut chat.input {
+                            if input.chars().count() < chat::MAX_CHAT_LEN {
+                                input.push(c);
+                            }
+                        }
+                    }
                     WindowEvent::CursorLeft { .. } => {
                         let mut cursor = world.write_storage::<Cursor>();
                         cursor.get_mut(cursor_entity).unwrap().hide();
@@ -217,6 +1027,91 @@ impl SimpleState for State {
     }
 }
 
+/// The game as the rules see it.
+fn position(world: &World) -> Position {
+    Position::new(
+        (*world.fetch::<Board>()).clone(),
+        *world.fetch::<Turn>(),
+        world.fetch::<BonusTurn>().0,
+    )
+}
+
+/// Whether the side to move may play at `pos`. Complains if it may not.
+fn check_move(world: &World, pos: &Coord) -> bool {
+    if world.fetch::<GameOver>().0 {
+        return false;
+    }
+    if let Err(err) = position(world).check(pos) {
+        info!("cannot play at {}: {}", pos, err);
+        world
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .single_write(GameEvent::InvalidClick(*pos));
+        return false;
+    }
+    true
+}
+
+/// Shows a chat message or emote in the chat panel, and records it.
+fn said(world: &World, message: &Message, ours: bool) {
+    let (side, text, entry) = match message {
+        Message::Chat { side, text } => (*side, text.clone(), Entry::Chat(*side, text.clone())),
+        Message::Emote { side, emote } => (*side, emote.to_string(), Entry::Emote(*side, *emote)),
+        _ => return,
+    };
+    info!("{:?} says {:?}", side, text);
+    world.fetch_mut::<Chat>().push(side, text, ours);
+    world.fetch_mut::<GameRecord>().entries.push(entry);
+}
+
+/// Ends the game, lost by `side` for staying away.
+fn forfeit(world: &World, side: Piece) {
+    end_game(world, format!("{:?} forfeits", side));
+}
+
+/// Ends the game, lost by `side` for giving up, and records it.
+fn resigned(world: &World, side: Piece) {
+    if world.fetch::<GameOver>().0 {
+        return;
+    }
+    world
+        .fetch_mut::<GameRecord>()
+        .entries
+        .push(Entry::Resigned(side));
+    end_game(world, format!("{:?} resigns", side));
+}
+
+/// Ends the game, lost by `side` on time, and records it.
+fn out_of_time(world: &World, side: Piece) {
+    if world.fetch::<GameOver>().0 {
+        return;
+    }
+    world
+        .fetch_mut::<GameRecord>()
+        .entries
+        .push(Entry::OutOfTime(side));
+    end_game(world, format!("{:?} ran out of time", side));
+}
+
+/// Ends the game before the board is full, telling why.
+fn end_game(world: &World, status: String) {
+    if world.fetch::<GameOver>().0 {
+        return;
+    }
+    info!("game over: {}", status);
+    world.fetch_mut::<GameOver>().0 = true;
+    world.fetch_mut::<Status>().0 = status;
+    world
+        .fetch_mut::<EventChannel<GameEvent>>()
+        .single_write(GameEvent::GameOver);
+}
+
+/// Whether it is the engine's turn to play.
+fn computer_to_move(world: &World) -> bool {
+    world.fetch::<AiConfig>().opponent
+        && *world.fetch::<Turn>() == Turn::Computer
+        && !world.fetch::<GameOver>().0
+}
+
 fn load_sprite_sheet(world: &mut World, texture: &str, sprite_sheet: &str) -> SpriteSheetHandle {
     let texture_handle = {
         let texture_storage = world.read_resource::<AssetStorage<Texture>>();
@@ -246,10 +1141,54 @@ fn initialize_camara(world: &mut World) {
 }
 
 fn main() -> amethyst::Result<()> {
-    amethyst::start_logger(Default::default());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut logger = LoggerConfig::default();
+    if args.first().map(String::as_str) == Some("brain") {
+        // A brain answers on stdout.
+        logger.stdout = StdoutLog::Off;
+    }
+    amethyst::start_logger(logger);
 
     let app_root = amethyst::utils::application_root_dir()?;
-    let display_config_path = app_root.join("config").join("display.ron");
+    let config_dir = app_root.join("config");
+    let net_config = || NetConfig::load(config_dir.join("net.ron"));
+    let session = match args.first().map(String::as_str) {
+        Some("host") => {
+            let addr = resolve(args.get(1).map_or(LOOPBACK, String::as_str))?;
+            let name = args.get(2).cloned().unwrap_or_else(default_name);
+            Some(Session::host(addr, name, &net_config()?)?)
+        }
+        Some(command @ "join") | Some(command @ "watch") => {
+            let addr = match args.get(1) {
+                Some(addr) => addr.clone(),
+                None => cli::choose_game(command == "watch")?.to_string(),
+            };
+            Some(Session::join(&addr, command == "watch", &net_config()?)?)
+        }
+        Some(command) => return cli::run(command, &args[1..], &app_root),
+        None => None,
+    };
+    let api = match net_config()?.api_port {
+        Some(port) => Some(Api::bind(port)?),
+        None => None,
+    };
+    let display_config_path = config_dir.join("display.ron");
+    let audio_config = AudioConfig::load(config_dir.join("audio.ron"))?;
+    let clock_config = ClockConfig::load(config_dir.join("clock.ron"))?;
+    clock_config.validate()?;
+    let animation_config = AnimationConfig::load(config_dir.join("animation.ron"))?;
+    let mut ai_config = AiConfig::load(config_dir.join("ai.ron"))?;
+    if session.is_some() {
+        // The other side is played over the network.
+        ai_config.opponent = false;
+    }
+    let book = match &ai_config.book {
+        Some(file) => Book::load(config_dir.join(file)).unwrap_or_else(|err| {
+            warn!("playing without an opening book: {}", err);
+            Book::default()
+        }),
+        None => Book::default(),
+    };
 
     let game_data = GameDataBuilder::default()
         .with_bundle(
@@ -258,15 +1197,75 @@ fn main() -> amethyst::Result<()> {
                     RenderToWindow::from_config_path(display_config_path)?
                         .with_clear([0.0, 0.0, 0.0, 1.0]),
                 )
-                .with_plugin(RenderFlat2D::default()),
+                .with_plugin(RenderFlat2D::default())
+                .with_plugin(RenderUi::default()),
         )?
         .with_bundle(TransformBundle::new())?
+        .with_bundle(InputBundle::<StringBindings>::new())?
+        .with_bundle(UiBundle::<StringBindings>::new())?
+        .with_bundle(AudioBundle::default())?
+        .with_system_desc(
+            DjSystemDesc::new(|music: &mut Music| music.music.next()),
+            "dj system",
+            &[],
+        )
         .with(cursor::CursorSystem, "cursor system", &[])
         .with(board::PieceSystem, "piece system", &[])
-        .with(blink::BlinkSystem, "blink system", &[]);
+        .with(
+            sync::PieceSyncSystem::default(),
+            "piece sync system",
+            &["piece system"],
+        )
+        .with(
+            marker::MarkerSystem::default(),
+            "marker system",
+            &["piece system"],
+        )
+        .with(preview::PreviewSystem, "preview system", &["piece system"])
+        .with(
+            preview::ThreatOverlaySystem,
+            "threat overlay system",
+            &["piece system"],
+        )
+        .with(
+            hint::HintSystem::default(),
+            "hint system",
+            &["piece system"],
+        )
+        .with(
+            highlight::HighlightSystem::default(),
+            "highlight system",
+            &["preview system", "threat overlay system", "hint system"],
+        )
+        .with(
+            anim::AnimationSystem,
+            "animation system",
+            &["piece sync system"],
+        )
+        .with(sound::SoundSystem::default(), "sound system", &[])
+        .with(record::RecordSystem::default(), "record system", &[])
+        .with(net::ApiSystem::default(), "api system", &[])
+        .with(
+            clock::ClockSystem::default(),
+            "clock system",
+            &["piece system"],
+        )
+        .with(hud::HudSystem, "hud system", &["clock system"])
+        .with(chat::ChatSystem, "chat system", &[]);
 
     let assets_dir = app_root.join("assets");
-    let mut game = Application::new(assets_dir, State::new(), game_data)?;
+    let state = State::new(session, app_root.join("records"));
+    let mut builder = Application::build(assets_dir, state)?
+        .with_resource(audio_config)
+        .with_resource(animation_config)
+        .with_resource(ai_config)
+        .with_resource(book)
+        .with_resource(SharedEngine::default())
+        .with_resource(Clock::new(clock_config, Instant::now()));
+    if let Some(api) = api {
+        builder = builder.with_resource(api);
+    }
+    let mut game = builder.build(game_data)?;
     game.run();
 
     Ok(())
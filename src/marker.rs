@@ -0,0 +1,165 @@
+use amethyst::assets::{AssetStorage, Loader};
+use amethyst::core::math::Vector3;
+use amethyst::core::{Hidden, Transform};
+use amethyst::ecs::*;
+use amethyst::renderer::sprite::SpriteSheetHandle;
+use amethyst::renderer::SpriteRender;
+use amethyst::ui::{
+    get_default_font, Anchor, FontAsset, FontHandle, LineMode, UiText, UiTransform,
+};
+use std::collections::HashMap;
+
+use crate::board::{Board, Piece, PieceRender};
+use crate::{ARENA_HEIGHT, ARENA_WIDTH, GRID_OFFSET};
+
+const MOVE_NUMBER_FONT_SIZE: f32 = 10.0;
+
+/// Whether move numbers are drawn on the stones.
+#[derive(Default)]
+pub struct ShowMoveNumbers(pub bool);
+
+pub struct Markers {
+    pub last_move: Entity,
+    pub font: FontHandle,
+}
+
+pub fn initialize_markers(world: &mut World, sprite_sheet_handle: SpriteSheetHandle) {
+    let font = get_default_font(
+        &world.read_resource::<Loader>(),
+        &world.read_resource::<AssetStorage<FontAsset>>(),
+    );
+    let last_move = world
+        .create_entity()
+        .with(SpriteRender::new(sprite_sheet_handle, 0))
+        .with(Transform::default())
+        .with(Hidden)
+        .build();
+    world.insert(Markers { last_move, font });
+    world.insert(ShowMoveNumbers(false));
+}
+
+/// Puts a dot of the opposite color on the last placed stone, and the move
+/// numbers on every stone when `ShowMoveNumbers` is on.
+#[derive(Default)]
+pub struct MarkerSystem {
+    numbers: HashMap<usize, Entity>,
+}
+
+impl PieceRender for MarkerSystem {}
+
+impl<'a> System<'a> for MarkerSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Transform>,
+        ReadExpect<'a, Board>,
+        ReadExpect<'a, Markers>,
+        Read<'a, ShowMoveNumbers>,
+        WriteStorage<'a, SpriteRender>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Hidden>,
+        WriteStorage<'a, UiTransform>,
+        WriteStorage<'a, UiText>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            default_trans,
+            board,
+            markers,
+            show_numbers,
+            mut renderers,
+            mut transforms,
+            mut hiddens,
+            mut ui_transforms,
+            mut ui_texts,
+        ) = data;
+
+        let marker = markers.last_move;
+        match board.last_move() {
+            Some(pos) => {
+                let piece = board.get_piece(&pos).expect("last move is not on board");
+                if let Some(renderer) = renderers.get_mut(marker) {
+                    Self::setup_renderer(renderer, piece.next().idx());
+                }
+                let mut transform = Self::setup_transform(&*default_trans, &pos);
+                transform.append_translation_xyz(0.0, 0.0, 0.1);
+                transform.set_scale(Vector3::from_element(default_trans.scale().x * 0.3));
+                transforms
+                    .insert(marker, transform)
+                    .expect("unable to insert transform");
+                hiddens.remove(marker);
+            }
+            None => {
+                hiddens
+                    .insert(marker, Hidden)
+                    .expect("unable to insert hidden");
+            }
+        }
+
+        let number_at = |idx| {
+            if show_numbers.0 {
+                board.move_number(idx)
+            } else {
+                None
+            }
+        };
+        self.numbers.retain(|idx, entity| {
+            if number_at(*idx).is_some() {
+                return true;
+            }
+            entities.delete(*entity).expect("unable to delete entity");
+            false
+        });
+        for (idx, piece) in board.pieces() {
+            let text = match number_at(idx) {
+                Some(number) => number.to_string(),
+                None => continue,
+            };
+            let color = text_color(piece);
+            if let Some(entity) = self.numbers.get(&idx) {
+                if let Some(ui_text) = ui_texts.get_mut(*entity) {
+                    if ui_text.text != text {
+                        ui_text.text = text;
+                    }
+                    ui_text.color = color;
+                }
+                continue;
+            }
+            let pos = board.idx2pos(idx);
+            let ui_transform = UiTransform::new(
+                format!("move number {}", idx),
+                Anchor::Middle,
+                Anchor::Middle,
+                pos.x as f32 * GRID_OFFSET / ARENA_WIDTH,
+                pos.y as f32 * GRID_OFFSET / ARENA_HEIGHT,
+                1.0,
+                GRID_OFFSET / ARENA_WIDTH,
+                GRID_OFFSET / ARENA_HEIGHT,
+            )
+            .into_percent()
+            .into_transparent();
+            let ui_text = UiText::new(
+                markers.font.clone(),
+                text,
+                color,
+                MOVE_NUMBER_FONT_SIZE,
+                LineMode::Single,
+                Anchor::Middle,
+            );
+            let entity = entities
+                .build_entity()
+                .with(ui_transform, &mut ui_transforms)
+                .with(ui_text, &mut ui_texts)
+                .build();
+            self.numbers.insert(idx, entity);
+        }
+    }
+}
+
+fn text_color(piece: Piece) -> [f32; 4] {
+    match piece {
+        Piece::Black => [1.0, 1.0, 1.0, 1.0],
+        Piece::White => [0.0, 0.0, 0.0, 1.0],
+    }
+}
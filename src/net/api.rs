@@ -0,0 +1,289 @@
+use amethyst::ecs::*;
+use amethyst::shrev::{EventChannel, ReaderId};
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Error as WsError, Message as WsMessage};
+
+use super::accept_streams;
+use crate::board::{Board, Piece};
+use crate::cursor::Coord;
+use crate::event::GameEvent;
+use crate::{BonusTurn, GameOver, Turn};
+
+/// How long a client thread waits for a request before sending updates.
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Sent by an API client, as JSON text such as `{"type":"place","x":0,"y":0}`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Places a stone of the side to move on an empty cell.
+    Place { x: i32, y: i32 },
+    /// Captures an opponent stone in a bonus turn, putting one's own there.
+    Capture { x: i32, y: i32 },
+    /// Asks for a `State` update.
+    State,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Stone {
+    pub x: i32,
+    pub y: i32,
+    pub piece: Piece,
+}
+
+/// Sent to API clients, as JSON text tagged with its `type`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Update {
+    /// The whole game. Sent on connecting, on request, and whenever it
+    /// changes, once a completed five-in-a-row is off the board.
+    State {
+        stones: Vec<Stone>,
+        side: Piece,
+        bonus: bool,
+        over: bool,
+    },
+    Placed(Stone),
+    /// An opponent stone captured in a bonus turn.
+    Captured(Stone),
+    FiveInARow {
+        piece: Piece,
+        cells: Vec<[i32; 2]>,
+    },
+    GameOver,
+    /// A request that could not be carried out.
+    Rejected {
+        reason: String,
+    },
+}
+
+impl Update {
+    pub fn state(board: &Board, turn: Turn, bonus: bool, over: bool) -> Self {
+        let mut stones: Vec<_> = board.pieces().collect();
+        stones.sort_by_key(|(idx, _)| *idx);
+        Update::State {
+            stones: stones
+                .into_iter()
+                .map(|(idx, piece)| Stone::new(&board.idx2pos(idx), piece))
+                .collect(),
+            side: turn.piece(),
+            bonus,
+            over,
+        }
+    }
+}
+
+impl Stone {
+    pub fn new(pos: &Coord, piece: Piece) -> Self {
+        Self {
+            x: pos.x,
+            y: pos.y,
+            piece,
+        }
+    }
+}
+
+struct Client {
+    id: u64,
+    requests: Receiver<Request>,
+    updates: Sender<String>,
+}
+
+/// A WebSocket endpoint on this machine, for bots and browser viewers. It
+/// is polled for requests by the game, and sent every update by `ApiSystem`.
+pub struct Api {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    next_id: u64,
+}
+
+impl Api {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        info!("serving the API on ws://127.0.0.1:{}", port);
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Accepts new clients, and returns the requests received since the
+    /// last poll, with the id of the client that sent each. A new client
+    /// asks for the state first.
+    pub fn poll(&mut self) -> Vec<(u64, Request)> {
+        for stream in accept_streams(&self.listener) {
+            let (request_sender, requests) = unbounded();
+            let (updates, update_receiver) = unbounded();
+            let id = self.next_id;
+            self.next_id += 1;
+            let _ = request_sender.send(Request::State);
+            thread::spawn(move || serve(stream, request_sender, update_receiver));
+            self.clients.push(Client {
+                id,
+                requests,
+                updates,
+            });
+        }
+        let mut received = Vec::new();
+        self.clients.retain(|client| loop {
+            match client.requests.try_recv() {
+                Ok(request) => received.push((client.id, request)),
+                Err(TryRecvError::Empty) => break true,
+                Err(TryRecvError::Disconnected) => break false,
+            }
+        });
+        received
+    }
+
+    pub fn send(&self, id: u64, update: &Update) {
+        let text = serde_json::to_string(update).expect("unable to encode update");
+        for client in self.clients.iter().filter(|client| client.id == id) {
+            let _ = client.updates.send(text.clone());
+        }
+    }
+
+    pub fn broadcast(&self, update: &Update) {
+        let text = serde_json::to_string(update).expect("unable to encode update");
+        for client in self.clients.iter() {
+            let _ = client.updates.send(text.clone());
+        }
+    }
+}
+
+/// Talks to one client until it leaves, on a thread of its own.
+fn serve(stream: TcpStream, requests: Sender<Request>, updates: Receiver<String>) {
+    let addr = stream.peer_addr().ok();
+    let mut socket = match stream
+        .set_nonblocking(false)
+        .map_err(|err| err.to_string())
+        .and_then(|_| tungstenite::accept(stream).map_err(|err| err.to_string()))
+    {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!("no WebSocket handshake with {:?}: {}", addr, err);
+            return;
+        }
+    };
+    info!("API client {:?} connected", addr);
+    if let Err(err) = socket.get_ref().set_read_timeout(Some(READ_TIMEOUT)) {
+        error!("unable to serve {:?}: {}", addr, err);
+        return;
+    }
+    loop {
+        match socket.read_message() {
+            Ok(WsMessage::Text(text)) => match serde_json::from_str(&text) {
+                Ok(request) => {
+                    if requests.send(request).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    let update = Update::Rejected {
+                        reason: format!("unreadable request: {}", err),
+                    };
+                    let text = serde_json::to_string(&update).expect("unable to encode update");
+                    if socket.write_message(WsMessage::Text(text)).is_err() {
+                        break;
+                    }
+                }
+            },
+            Ok(WsMessage::Close(_)) => break,
+            Ok(_) => {}
+            Err(WsError::Io(err))
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut => {}
+            Err(_) => break,
+        }
+        loop {
+            match updates.try_recv() {
+                Ok(text) => {
+                    if socket.write_message(WsMessage::Text(text)).is_err() {
+                        info!("API client {:?} left", addr);
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+    }
+    info!("API client {:?} left", addr);
+}
+
+/// Tells API clients what happens in the game.
+#[derive(Default)]
+pub struct ApiSystem {
+    reader: Option<ReaderId<GameEvent>>,
+    /// Whether a five-in-a-row is blinking, during which the turn is not
+    /// settled yet.
+    blinking: bool,
+    last: Option<Update>,
+}
+
+impl<'a> System<'a> for ApiSystem {
+    type SystemData = (
+        Read<'a, EventChannel<GameEvent>>,
+        ReadExpect<'a, Board>,
+        ReadExpect<'a, Turn>,
+        ReadExpect<'a, BonusTurn>,
+        ReadExpect<'a, GameOver>,
+        Option<Read<'a, Api>>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (events, board, turn, bonus, game_over, api): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("api system is not set up");
+        let events = events.read(reader);
+        let api = match api {
+            Some(api) => api,
+            None => return,
+        };
+        for event in events {
+            match event {
+                GameEvent::FiveInARow(..) => self.blinking = true,
+                GameEvent::FiveRemoved(..) => self.blinking = false,
+                _ => {}
+            }
+            let update = match *event {
+                GameEvent::PiecePlaced(piece, pos) => Update::Placed(Stone::new(&pos, piece)),
+                GameEvent::Captured(piece, pos) => Update::Captured(Stone::new(&pos, piece)),
+                GameEvent::FiveInARow(piece, five) => Update::FiveInARow {
+                    piece,
+                    cells: five.iter().map(|pos| [pos.x, pos.y]).collect(),
+                },
+                GameEvent::GameOver => Update::GameOver,
+                GameEvent::InvalidClick(..)
+                | GameEvent::HintShown(..)
+                | GameEvent::FiveRemoved(..)
+                | GameEvent::Undone
+                | GameEvent::NewGame
+                | GameEvent::Restored => continue,
+            };
+            api.broadcast(&update);
+        }
+        if self.blinking || board.has_five_in_a_row() {
+            return;
+        }
+        let state = Update::state(&board, *turn, bonus.0, game_over.0);
+        if self.last.as_ref() != Some(&state) {
+            api.broadcast(&state);
+            self.last = Some(state);
+        }
+    }
+}
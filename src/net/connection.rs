@@ -0,0 +1,99 @@
+use crossbeam_channel::{unbounded, Receiver, TryRecvError};
+use log::{error, info};
+use std::cell::Cell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::Message;
+
+/// How long a connection may stay quiet before a `Ping` is sent on it.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long without hearing anything, not even a `Ping`, before the peer is
+/// taken for gone. Without it, a connection dropped without a word would
+/// never be noticed.
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A peer on the other end of a TCP stream. Incoming messages are read on a
+/// thread of their own, so polling never blocks the frame loop.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<Message>,
+    addr: SocketAddr,
+    last_sent: Cell<Instant>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let addr = stream.peer_addr()?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = unbounded();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        info!("connection to {} closed: {}", addr, err);
+                        break;
+                    }
+                };
+                match serde_json::from_str(&line) {
+                    Ok(Message::Ping) => {}
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => error!("unreadable message from {}: {}", addr, err),
+                }
+            }
+        });
+        Ok(Self {
+            stream,
+            incoming,
+            addr,
+            last_sent: Cell::new(Instant::now()),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        (&self.stream).write_all(line.as_bytes())?;
+        self.last_sent.set(Instant::now());
+        Ok(())
+    }
+
+    /// The next message, if one has arrived, or an error once the peer is gone.
+    /// Sends a `Ping` if nothing was sent for a while, so that the peer
+    /// knows we are still there.
+    pub fn poll(&self) -> Result<Option<Message>, TryRecvError> {
+        if self.last_sent.get().elapsed() >= HEARTBEAT_INTERVAL {
+            if let Err(err) = self.send(&Message::Ping) {
+                // The reader notices too, and a later poll reports it.
+                error!("unable to send to {}: {}", self.addr, err);
+                self.last_sent.set(Instant::now());
+            }
+        }
+        match self.incoming.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // The reader thread holds a clone of the stream, which would
+        // otherwise keep it open.
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
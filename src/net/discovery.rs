@@ -0,0 +1,241 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::PROTOCOL_VERSION;
+use crate::board::BOARD_HALF_WIDTH;
+
+/// Games are announced to this UDP port.
+pub const DISCOVERY_PORT: u16 = 4716;
+/// Reaches every lobby on this machine, where a datagram sent to
+/// `127.0.0.1` would only reach one of those sharing the port.
+const LOOPBACK_BROADCAST: Ipv4Addr = Ipv4Addr::new(127, 255, 255, 255);
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// A game not heard of for this long is gone from the lobby.
+const EXPIRY: Duration = Duration::from_secs(4);
+const MAX_DATAGRAM: usize = 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to look for a game to join, when not given an address.
+pub const DISCOVERY_WAIT: Duration = Duration::from_secs(3);
+
+/// What a hosted game tells the local network about itself.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Announcement {
+    pub version: u32,
+    pub name: String,
+    /// The TCP port to join the game on.
+    pub port: u16,
+    /// Cells on each side of the board.
+    pub board_size: u32,
+    pub variant: String,
+    pub players: usize,
+    pub spectators: usize,
+}
+
+impl Announcement {
+    pub fn is_full(&self) -> bool {
+        self.players >= 2
+    }
+}
+
+/// Broadcasts a game's announcement every second, to the local network, to
+/// this machine, or to both for a game hosted on every interface.
+pub struct Beacon {
+    socket: UdpSocket,
+    /// Where the announcements go, and whether the last one failed to.
+    targets: Vec<(Ipv4Addr, bool)>,
+    name: String,
+    port: u16,
+    last: Option<Instant>,
+}
+
+impl Beacon {
+    /// A beacon for the game hosted at `addr`, which it announces from, so
+    /// that the game is joined at the address it is hosted at. A game
+    /// hosted at a network address is seen on this machine too, since a
+    /// broadcast reaches the machine it is sent from.
+    pub fn new(name: String, addr: SocketAddr) -> io::Result<Self> {
+        let ip = match addr.ip() {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "games are only announced over IPv4",
+                ))
+            }
+        };
+        let socket = UdpSocket::bind((ip, 0))?;
+        socket.set_broadcast(true)?;
+        // A socket bound to a network address cannot send to loopback, nor
+        // one bound to loopback to the network.
+        let targets = if ip.is_loopback() {
+            vec![LOOPBACK_BROADCAST]
+        } else if ip.is_unspecified() {
+            vec![Ipv4Addr::BROADCAST, LOOPBACK_BROADCAST]
+        } else {
+            vec![Ipv4Addr::BROADCAST]
+        };
+        Ok(Self {
+            socket,
+            targets: targets.into_iter().map(|ip| (ip, false)).collect(),
+            name,
+            port: addr.port(),
+            last: None,
+        })
+    }
+
+    /// Announces the game if it has not been for a while.
+    pub fn tick(&mut self, players: usize, spectators: usize) {
+        if self
+            .last
+            .map_or(false, |last| last.elapsed() < ANNOUNCE_INTERVAL)
+        {
+            return;
+        }
+        self.last = Some(Instant::now());
+        let announcement = Announcement {
+            version: PROTOCOL_VERSION,
+            name: self.name.clone(),
+            port: self.port,
+            board_size: (BOARD_HALF_WIDTH * 2 + 1) as u32,
+            variant: "endless".to_string(),
+            players,
+            spectators,
+        };
+        let datagram = serde_json::to_vec(&announcement).expect("unable to encode announcement");
+        for (ip, failing) in self.targets.iter_mut() {
+            match self.socket.send_to(&datagram, (*ip, DISCOVERY_PORT)) {
+                // Told once, rather than every second for the whole game.
+                Err(err) if !*failing => {
+                    error!("unable to announce the game to {}: {}", ip, err);
+                    *failing = true;
+                }
+                Err(_) => {}
+                Ok(_) if *failing => {
+                    info!("announcing the game to {} again", ip);
+                    *failing = false;
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+/// Games announced on the local network, with the address to join them at.
+pub struct Lobby {
+    socket: UdpSocket,
+    games: Vec<(SocketAddr, Announcement, Instant)>,
+}
+
+impl Lobby {
+    /// A lobby sharing the port with any other on this machine, so that
+    /// several clients can look for games at once.
+    pub fn new() -> io::Result<Self> {
+        let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).into())?;
+        let socket = socket.into_udp_socket();
+        socket.set_nonblocking(true)?;
+        info!("looking for games on port {}", DISCOVERY_PORT);
+        Ok(Self {
+            socket,
+            games: Vec::new(),
+        })
+    }
+
+    /// Reads the announcements received since the last poll, and forgets
+    /// the games that stopped announcing themselves.
+    pub fn poll(&mut self) {
+        let mut buf = [0; MAX_DATAGRAM];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    error!("unable to receive announcements: {}", err);
+                    break;
+                }
+            };
+            let announcement: Announcement = match serde_json::from_slice(&buf[..len]) {
+                Ok(announcement) => announcement,
+                Err(err) => {
+                    error!("unreadable announcement from {}: {}", from, err);
+                    continue;
+                }
+            };
+            if announcement.version != PROTOCOL_VERSION {
+                continue;
+            }
+            let addr = SocketAddr::new(from.ip(), announcement.port);
+            let now = Instant::now();
+            match self.games.iter_mut().find(|(known, ..)| *known == addr) {
+                Some(game) => *game = (addr, announcement, now),
+                None => {
+                    info!("found {:?} at {}", announcement.name, addr);
+                    self.games.push((addr, announcement, now));
+                }
+            }
+        }
+        self.games.retain(|(.., seen)| seen.elapsed() < EXPIRY);
+    }
+
+    pub fn games(&self) -> impl Iterator<Item = (&SocketAddr, &Announcement)> {
+        self.games
+            .iter()
+            .map(|(addr, announcement, _)| (addr, announcement))
+    }
+}
+
+/// Listens for `wait`, and returns the games announced meanwhile.
+pub fn find_games(wait: Duration) -> io::Result<Vec<(SocketAddr, Announcement)>> {
+    let mut lobby = Lobby::new()?;
+    let start = Instant::now();
+    while start.elapsed() < wait {
+        lobby.poll();
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(lobby
+        .games()
+        .map(|(addr, announcement)| (*addr, announcement.clone()))
+        .collect())
+}
+
+/// The name a game is announced with, unless given one.
+pub fn default_name() -> String {
+    match std::env::var("USER").or_else(|_| std::env::var("USERNAME")) {
+        Ok(user) => format!("{}'s game", user),
+        Err(_) => "endless five".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lobby_finds_a_game_announced_on_loopback() {
+        let mut lobby = Lobby::new().expect("unable to look for games");
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 4799));
+        let mut beacon = Beacon::new("test".to_string(), addr).expect("unable to announce");
+        beacon.tick(1, 0);
+        let start = Instant::now();
+        let game = loop {
+            lobby.poll();
+            if let Some((_, game)) = lobby.games().find(|(found, _)| **found == addr) {
+                break game.clone();
+            }
+            assert!(start.elapsed() < Duration::from_secs(2), "no game found");
+            thread::sleep(POLL_INTERVAL);
+        };
+        assert_eq!(game.name, "test");
+        assert_eq!(game.port, 4799);
+        assert_eq!(game.board_size, 15);
+        assert_eq!(game.variant, "endless");
+        assert_eq!(game.players, 1);
+        assert_eq!(game.spectators, 0);
+    }
+}
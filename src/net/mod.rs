@@ -0,0 +1,697 @@
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::ai::XorShift;
+use crate::board::Piece;
+use crate::cursor::Coord;
+use crate::rules::{IllegalMove, Position};
+
+mod api;
+mod connection;
+mod discovery;
+mod protocol;
+mod server;
+
+pub use api::{Api, ApiSystem, Request, Update};
+pub use connection::Connection;
+pub use discovery::{default_name, find_games, Announcement, Beacon, DISCOVERY_WAIT};
+pub use protocol::{GameState, Message, PROTOCOL_VERSION};
+pub use server::Server;
+
+pub const DEFAULT_PORT: u16 = 4715;
+/// Where a game is hosted unless given an address, out of reach of the
+/// network.
+pub const LOOPBACK: &str = "127.0.0.1";
+/// How often a guest who lost the connection tries to get it back.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long one attempt to reconnect may hold up a frame.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NetConfig {
+    /// How many seconds a player who lost the connection has to come back
+    /// before forfeiting the game.
+    pub reconnect_grace: f32,
+    /// The port of the WebSocket API on this machine, if it is served.
+    pub api_port: Option<u16>,
+}
+
+impl NetConfig {
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs_f32(self.reconnect_grace)
+    }
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_grace: 30.0,
+            api_port: None,
+        }
+    }
+}
+
+pub enum NetEvent {
+    /// A guest was welcomed to play.
+    Connected(SocketAddr),
+    /// A spectator was welcomed.
+    Watching(SocketAddr),
+    Message(Message),
+    /// The connection to the guest, or to the host, was lost. It may come
+    /// back within the grace period.
+    Away,
+    /// The guest, or the host, is back.
+    Back(SocketAddr),
+    /// The guest stayed away past the grace period.
+    Forfeited,
+    /// The other end left for good.
+    Disconnected,
+}
+
+/// This instance's end of a game played over the network: the host, who
+/// keeps the game and waits for a guest and spectators, or a guest or
+/// spectator who joined it.
+pub struct Session {
+    /// The side played on this machine, `None` while watching, or until the
+    /// host welcomes us.
+    pub local: Option<Piece>,
+    listener: Option<TcpListener>,
+    /// The host's guest, or the guest's host.
+    peer: Option<Connection>,
+    /// Connections that have not said `Hello` yet.
+    pending: Vec<Connection>,
+    spectators: Vec<Connection>,
+    /// Announces a hosted game on the local network.
+    beacon: Option<Beacon>,
+    /// What the guest comes back with after losing the connection.
+    token: Option<u64>,
+    /// The host to get back to, for a guest.
+    host: Option<SocketAddr>,
+    watch: bool,
+    /// Since when the connection is lost, while it may still come back.
+    away: Option<Instant>,
+    last_attempt: Option<Instant>,
+    grace: Duration,
+    rng: XorShift,
+}
+
+impl Session {
+    /// Hosts a game at `addr`, on a port of the system's choosing if its
+    /// port is 0.
+    pub fn host(addr: SocketAddr, name: String, config: &NetConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        info!("hosting {:?} at {}", name, addr);
+        Ok(Self {
+            local: Some(Piece::Black),
+            listener: Some(listener),
+            beacon: beacon(name, addr),
+            ..Self::new(config)
+        })
+    }
+
+    /// Connects to a host at `addr`, as `resolve` reads it, to play if a
+    /// side is left, or to watch. The side played is set once the host
+    /// welcomes us.
+    pub fn join(addr: &str, watch: bool, config: &NetConfig) -> io::Result<Self> {
+        let host = resolve(addr)?;
+        let peer = Connection::new(TcpStream::connect(host)?)?;
+        info!("joined the game at {}", host);
+        peer.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            watch,
+            token: None,
+        })?;
+        Ok(Self {
+            peer: Some(peer),
+            host: Some(host),
+            watch,
+            ..Self::new(config)
+        })
+    }
+
+    fn new(config: &NetConfig) -> Self {
+        Self {
+            local: None,
+            listener: None,
+            peer: None,
+            pending: Vec::new(),
+            spectators: Vec::new(),
+            beacon: None,
+            token: None,
+            host: None,
+            watch: false,
+            away: None,
+            last_attempt: None,
+            grace: config.reconnect_grace(),
+            rng: XorShift::from_time(),
+        }
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// The address the host takes guests at.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+    }
+
+    /// The other end's side, as long as this one plays.
+    pub fn remote(&self) -> Option<Piece> {
+        self.local.map(|local| local.next())
+    }
+
+    /// Takes the host's `Welcome`, as a guest. A token already given is
+    /// kept unless the host gives a new one.
+    pub fn welcomed(&mut self, side: Option<Piece>, token: Option<u64>) {
+        self.local = side;
+        self.token = token.or(self.token);
+    }
+
+    /// Accepts and welcomes newcomers, handing them `state` to start from,
+    /// tries to get a lost connection back, and returns what happened since
+    /// the last poll.
+    pub fn poll<F: Fn() -> GameState>(&mut self, state: F) -> Vec<NetEvent> {
+        let mut events = Vec::new();
+        if let Some(listener) = &self.listener {
+            self.pending.extend(accept(listener));
+            let mut idx = 0;
+            while idx < self.pending.len() {
+                match self.pending[idx].poll() {
+                    Ok(Some(Message::Hello {
+                        version,
+                        watch,
+                        token,
+                    })) => {
+                        let connection = self.pending.remove(idx);
+                        let event = self.welcome(&connection, version, watch, token, &state);
+                        match event {
+                            Some(NetEvent::Watching(_)) => self.spectators.push(connection),
+                            Some(_) => {
+                                if let Some(stale) = self.peer.replace(connection) {
+                                    info!("dropped the stale connection to {}", stale.addr());
+                                }
+                            }
+                            None => {}
+                        }
+                        events.extend(event);
+                    }
+                    Ok(Some(message)) => {
+                        error!("unexpected {:?} before hello", message);
+                        self.pending.remove(idx);
+                    }
+                    Ok(None) => idx += 1,
+                    Err(_) => {
+                        self.pending.remove(idx);
+                    }
+                }
+            }
+            self.spectators.retain(|spectator| loop {
+                match spectator.poll() {
+                    Ok(Some(Message::Bye)) | Err(_) => {
+                        info!("{} stopped watching", spectator.addr());
+                        break false;
+                    }
+                    Ok(Some(message)) => error!("unexpected {:?} from a spectator", message),
+                    Ok(None) => break true,
+                }
+            });
+        }
+        if let Some(beacon) = &mut self.beacon {
+            // A guest who lost the connection keeps the seat.
+            let players = if self.peer.is_some() || self.away.is_some() {
+                2
+            } else {
+                1
+            };
+            beacon.tick(players, self.spectators.len());
+        }
+        if let Some(peer) = &self.peer {
+            loop {
+                match peer.poll() {
+                    Ok(Some(Message::Bye)) => {
+                        info!("{} left", peer.addr());
+                        self.peer = None;
+                        self.token = None;
+                        events.push(NetEvent::Disconnected);
+                        break;
+                    }
+                    Ok(Some(message)) => events.push(NetEvent::Message(message)),
+                    Ok(None) => break,
+                    Err(_) => {
+                        info!("lost the connection to {}", peer.addr());
+                        self.peer = None;
+                        self.away = Some(Instant::now());
+                        events.push(NetEvent::Away);
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(away) = self.away {
+            if away.elapsed() > self.grace {
+                self.away = None;
+                if self.is_host() {
+                    info!("the guest did not come back");
+                    self.token = None;
+                    events.push(NetEvent::Forfeited);
+                } else {
+                    info!("gave up reconnecting");
+                    events.push(NetEvent::Disconnected);
+                }
+            } else if !self.is_host() {
+                events.extend(self.reconnect());
+            }
+        }
+        events
+    }
+
+    /// Answers a newcomer's `Hello`. Returns `None` if it was turned away.
+    fn welcome<F: Fn() -> GameState>(
+        &mut self,
+        connection: &Connection,
+        version: u32,
+        watch: bool,
+        token: Option<u64>,
+        state: &F,
+    ) -> Option<NetEvent> {
+        let addr = connection.addr();
+        if version != PROTOCOL_VERSION {
+            let reason = format!(
+                "protocol version {} expected, got {}",
+                PROTOCOL_VERSION, version
+            );
+            info!("turned {} away: {}", addr, reason);
+            let _ = connection.send(&Message::Rejected { reason });
+            return None;
+        }
+        // The seat of a guest who lost the connection is kept for them, even
+        // before the host notices that the old connection is gone.
+        let back = token.is_some() && token == self.token;
+        let seat = back || (self.peer.is_none() && self.away.is_none());
+        let side = match (watch, seat) {
+            (false, true) => self.remote(),
+            _ => None,
+        };
+        if side.is_some() && !back {
+            self.token = Some(self.rng.next());
+        }
+        let welcomed = connection
+            .send(&Message::Welcome {
+                version: PROTOCOL_VERSION,
+                side,
+                token: side.and(self.token),
+            })
+            .and_then(|_| connection.send(&Message::Sync(state())));
+        if let Err(err) = welcomed {
+            error!("unable to welcome {}: {}", addr, err);
+            return None;
+        }
+        match side {
+            Some(_) if back => {
+                info!("{} is back", addr);
+                self.away = None;
+                Some(NetEvent::Back(addr))
+            }
+            Some(_) => {
+                info!("{} joined", addr);
+                Some(NetEvent::Connected(addr))
+            }
+            None => {
+                info!("{} is watching", addr);
+                Some(NetEvent::Watching(addr))
+            }
+        }
+    }
+
+    /// Checks a move the guest asked for against `position`, as the host.
+    /// A move that is not the guest's to make is turned down with a
+    /// `Rejected`, followed by a `Sync` of `state`.
+    pub fn check_move<F: Fn() -> GameState>(
+        &mut self,
+        position: &Position,
+        game_over: bool,
+        pos: &Coord,
+        state: F,
+    ) -> bool {
+        let checked = if Some(position.side()) == self.local {
+            Err(IllegalMove::WrongSide)
+        } else if game_over {
+            Err(IllegalMove::GameOver)
+        } else {
+            position.check(pos)
+        };
+        match checked {
+            Ok(_) => true,
+            Err(err) => {
+                info!("rejected the guest's move at {}: {}", pos, err);
+                self.send(Message::Rejected {
+                    reason: err.to_string(),
+                });
+                self.send(Message::Sync(state()));
+                false
+            }
+        }
+    }
+
+    /// Tries to connect to the host again, once in a while.
+    fn reconnect(&mut self) -> Option<NetEvent> {
+        if self
+            .last_attempt
+            .map_or(false, |last| last.elapsed() < RECONNECT_INTERVAL)
+        {
+            return None;
+        }
+        self.last_attempt = Some(Instant::now());
+        let host = self.host?;
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            watch: self.watch,
+            token: self.token,
+        };
+        let peer = TcpStream::connect_timeout(&host, CONNECT_TIMEOUT)
+            .and_then(Connection::new)
+            .and_then(|peer| peer.send(&hello).map(|_| peer));
+        match peer {
+            Ok(peer) => {
+                info!("reconnected to {}", host);
+                self.peer = Some(peer);
+                self.away = None;
+                Some(NetEvent::Back(host))
+            }
+            Err(err) => {
+                info!("unable to reconnect to {}: {}", host, err);
+                None
+            }
+        }
+    }
+
+    /// Sends to the host, or to the guest.
+    pub fn send(&mut self, message: Message) {
+        if let Some(peer) = &self.peer {
+            if let Err(err) = peer.send(&message) {
+                // The reader notices too, and the next poll reports it.
+                error!("unable to send to {}: {}", peer.addr(), err);
+            }
+        }
+    }
+
+    /// Sends to the guest and to every spectator.
+    pub fn broadcast(&mut self, message: Message) {
+        self.tell_spectators(message.clone());
+        self.send(message);
+    }
+
+    /// Sends to every spectator, but not to the guest.
+    pub fn tell_spectators(&mut self, message: Message) {
+        self.spectators
+            .retain(|spectator| match spectator.send(&message) {
+                Ok(_) => true,
+                Err(err) => {
+                    error!("unable to send to {}: {}", spectator.addr(), err);
+                    false
+                }
+            });
+    }
+
+    pub fn disconnect(&mut self) {
+        self.broadcast(Message::Bye);
+        self.peer = None;
+        self.spectators.clear();
+        self.away = None;
+    }
+}
+
+/// The address `addr` stands for, on the default port unless it says
+/// otherwise. A port number alone stands for that port on this machine.
+pub fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    let addr = if let Ok(port) = addr.parse::<u16>() {
+        format!("{}:{}", Ipv4Addr::LOCALHOST, port)
+    } else if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, DEFAULT_PORT)
+    };
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", addr)))
+}
+
+/// A beacon announcing the game hosted at `addr`, unless the network does
+/// not allow it.
+fn beacon(name: String, addr: SocketAddr) -> Option<Beacon> {
+    Beacon::new(name, addr)
+        .map_err(|err| warn!("the game will not be announced: {}", err))
+        .ok()
+}
+
+/// Every connection waiting on `listener`.
+fn accept(listener: &TcpListener) -> Vec<Connection> {
+    accept_streams(listener)
+        .into_iter()
+        .filter_map(|stream| {
+            let addr = stream.peer_addr();
+            match stream
+                .set_nonblocking(false)
+                .and_then(|_| Connection::new(stream))
+            {
+                Ok(connection) => Some(connection),
+                Err(err) => {
+                    error!("unable to accept {:?}: {}", addr, err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Every stream waiting on the nonblocking `listener`.
+fn accept_streams(listener: &TcpListener) -> Vec<TcpStream> {
+    let mut streams = Vec::new();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => streams.push(stream),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(err) => {
+                error!("unable to accept a connection: {}", err);
+                break;
+            }
+        }
+    }
+    streams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, BOARD_HALF_WIDTH};
+    use crate::record::Entry;
+    use crate::Turn;
+    use std::thread;
+
+    /// How long the other end may take before the test fails.
+    const WAIT: Duration = Duration::from_secs(2);
+
+    /// Polls `session` until `count` events have come, handing newcomers
+    /// `position`.
+    fn events(session: &mut Session, position: &Position, count: usize) -> Vec<NetEvent> {
+        events_with(session, &GameState::from_position(position, None), count)
+    }
+
+    /// Polls `session` until `count` events have come, handing newcomers
+    /// `state`.
+    fn events_with(session: &mut Session, state: &GameState, count: usize) -> Vec<NetEvent> {
+        let start = Instant::now();
+        let mut events = Vec::new();
+        while events.len() < count {
+            assert!(start.elapsed() < WAIT, "only {} events came", events.len());
+            events.extend(session.poll(|| state.clone()));
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(events.len(), count);
+        events
+    }
+
+    fn messages(session: &mut Session, position: &Position, count: usize) -> Vec<Message> {
+        events(session, position, count)
+            .into_iter()
+            .map(|event| match event {
+                NetEvent::Message(message) => message,
+                _ => panic!("a message expected"),
+            })
+            .collect()
+    }
+
+    /// Plays the guest's next move if it is legal, as the host does.
+    fn answer(host: &mut Session, position: &mut Position) -> bool {
+        let pos = match messages(host, position, 1).pop() {
+            Some(Message::Move { pos }) => pos,
+            other => panic!("a move expected, got {:?}", other),
+        };
+        let legal = host.check_move(position, false, &pos, || {
+            GameState::from_position(position, None)
+        });
+        if legal {
+            position.play(&pos).expect("unable to play a checked move");
+            host.broadcast(Message::Moved { pos });
+        }
+        legal
+    }
+
+    fn moved(guest: &mut Session, position: &Position) -> Coord {
+        match messages(guest, position, 1).pop() {
+            Some(Message::Moved { pos }) => pos,
+            other => panic!("a move expected, got {:?}", other),
+        }
+    }
+
+    /// A host at `position` and a guest it welcomed, over loopback.
+    fn start(position: &Position) -> (Session, Session) {
+        let config = NetConfig::default();
+        let addr = "127.0.0.1:0".parse().expect("unable to parse the address");
+        let mut host =
+            Session::host(addr, "test".to_string(), &config).expect("unable to host a game");
+        let addr = host.local_addr().expect("no address to join at");
+        let mut guest =
+            Session::join(&addr.to_string(), false, &config).expect("unable to join the game");
+        assert!(matches!(
+            events(&mut host, position, 1)[..],
+            [NetEvent::Connected(_)]
+        ));
+        match &messages(&mut guest, position, 2)[..] {
+            [Message::Welcome {
+                version,
+                side,
+                token,
+            }, Message::Sync(state)] => {
+                assert_eq!(*version, PROTOCOL_VERSION);
+                assert_eq!(*side, Some(Piece::White));
+                assert!(token.is_some());
+                assert_eq!(*state, GameState::from_position(position, None));
+                guest.welcomed(*side, *token);
+            }
+            other => panic!("a welcome and a sync expected, got {:?}", other),
+        }
+        (host, guest)
+    }
+
+    #[test]
+    fn guest_plays_the_host_over_loopback() {
+        let mut board = Board::new(BOARD_HALF_WIDTH);
+        for y in 0..4 {
+            board.put_piece(&Coord::new_bounded(0, y), Piece::White);
+            board.put_piece(&Coord::new_bounded(2, y), Piece::Black);
+        }
+        let mut position = Position::new(board, Turn::Player, false);
+        let (mut host, mut guest) = start(&position);
+
+        let pos = Coord::new_bounded(5, 5);
+        position.play(&pos).expect("unable to play");
+        host.broadcast(Message::Moved { pos });
+        assert_eq!(moved(&mut guest, &position), pos);
+
+        // The guest completes a five, and captures in the bonus turn.
+        for (x, y) in [(0, 4), (2, 0)].iter() {
+            let pos = Coord::new_bounded(*x, *y);
+            guest.send(Message::Move { pos });
+            assert!(answer(&mut host, &mut position));
+            assert_eq!(moved(&mut guest, &position), pos);
+        }
+        assert_eq!(position.fives, [0, 1]);
+        assert_eq!(
+            position.board.get_piece(&Coord::new_bounded(2, 0)),
+            Some(&Piece::White)
+        );
+
+        // It is black's turn again.
+        guest.send(Message::Move {
+            pos: Coord::new_bounded(6, 6),
+        });
+        assert!(!answer(&mut host, &mut position));
+        match &messages(&mut guest, &position, 2)[..] {
+            [Message::Rejected { reason }, Message::Sync(state)] => {
+                assert_eq!(*reason, IllegalMove::WrongSide.to_string());
+                assert_eq!(*state, GameState::from_position(&position, None));
+            }
+            other => panic!("a rejection and a sync expected, got {:?}", other),
+        }
+
+        // The guest resigns, and a spectator joining after it finds the
+        // game over, resignation and all.
+        guest.send(Message::Resigned { side: Piece::White });
+        assert!(matches!(
+            messages(&mut host, &position, 1)[..],
+            [Message::Resigned { side: Piece::White }]
+        ));
+        let ended = GameState {
+            over: true,
+            moves: vec![Entry::Resigned(Piece::White)],
+            ..GameState::from_position(&position, None)
+        };
+        let addr = host.local_addr().expect("no address to join at");
+        let mut spectator = Session::join(&addr.to_string(), true, &NetConfig::default())
+            .expect("unable to watch the game");
+        assert!(matches!(
+            events_with(&mut host, &ended, 1)[..],
+            [NetEvent::Watching(_)]
+        ));
+        match &messages(&mut spectator, &position, 2)[..] {
+            [Message::Welcome { side: None, .. }, Message::Sync(state)] => {
+                assert_eq!(*state, ended);
+            }
+            other => panic!("a welcome and a sync expected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn token_takes_the_seat_back_from_a_stale_connection() {
+        let position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+        let (mut host, mut guest) = start(&position);
+        // The guest comes back on a new connection while the host still
+        // holds the old one, as after a drop the host did not notice.
+        let addr = host.local_addr().expect("no address to join at");
+        let again = TcpStream::connect(addr)
+            .and_then(Connection::new)
+            .expect("unable to connect again");
+        again
+            .send(&Message::Hello {
+                version: PROTOCOL_VERSION,
+                watch: false,
+                token: guest.token,
+            })
+            .expect("unable to say hello");
+        assert!(matches!(
+            events(&mut host, &position, 1)[..],
+            [NetEvent::Back(_)]
+        ));
+        let start = Instant::now();
+        let welcome = loop {
+            match again.poll().expect("the host is gone") {
+                Some(message) => break message,
+                None => assert!(start.elapsed() < WAIT, "no welcome came"),
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+        match welcome {
+            Message::Welcome { side, token, .. } => {
+                assert_eq!(side, Some(Piece::White));
+                assert_eq!(token, guest.token);
+            }
+            other => panic!("a welcome expected, got {:?}", other),
+        }
+        // The old connection is dropped, and the guest, who cannot tell it
+        // from a drop, reconnects at once.
+        assert!(matches!(
+            events(&mut guest, &position, 2)[..],
+            [NetEvent::Away, NetEvent::Back(_)]
+        ));
+    }
+}
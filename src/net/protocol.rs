@@ -0,0 +1,198 @@
+use amethyst::ecs::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+use crate::chat::Emote;
+use crate::cursor::Coord;
+use crate::history::History;
+use crate::offer::Offer;
+use crate::record::{Entry, GameRecord};
+use crate::rules::Position;
+use crate::{BonusTurn, GameOver, Turn};
+
+/// Bumped whenever a message changes, so that mismatched builds refuse to
+/// play rather than drift apart.
+pub const PROTOCOL_VERSION: u32 = 10;
+
+/// Everything needed to put a game back on the board.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GameState {
+    pub stones: Vec<(Coord, Piece)>,
+    /// The side to move.
+    pub side: Piece,
+    pub bonus: bool,
+    /// The five-in-a-row completed by the last move, which is shown again
+    /// to whoever the state is restored for.
+    pub five: Option<[Coord; 5]>,
+    /// Whether the game is over, by the rules or otherwise, so that it
+    /// stays over for whoever the state is restored for.
+    pub over: bool,
+    /// The moves of the game so far, and how it ended, as recorded, so that
+    /// the record of whoever the state is restored for is whole.
+    pub moves: Vec<Entry>,
+}
+
+impl GameState {
+    pub fn take(world: &World) -> Self {
+        let record = world.fetch::<GameRecord>();
+        let last = record
+            .entries
+            .iter()
+            .rev()
+            .find(|entry| !matches!(entry, Entry::Hint(..)) && !entry.is_talk());
+        let position = crate::position(world);
+        // Once blinked, the five is gone from the board.
+        let five = match last {
+            Some(Entry::FiveInARow(piece, five))
+                if five
+                    .iter()
+                    .all(|pos| position.board.get_piece(pos) == Some(piece)) =>
+            {
+                Some(*five)
+            }
+            _ => None,
+        };
+        Self {
+            moves: record
+                .entries
+                .iter()
+                .filter(|entry| entry.is_move() || entry.ends_game())
+                .cloned()
+                .collect(),
+            over: world.fetch::<GameOver>().0,
+            ..Self::from_position(&position, five)
+        }
+    }
+
+    pub fn from_position(position: &Position, five: Option<[Coord; 5]>) -> Self {
+        let board = &position.board;
+        let mut stones: Vec<_> = board.pieces().collect();
+        stones.sort_by_key(|(idx, _)| *idx);
+        Self {
+            stones: stones
+                .into_iter()
+                .map(|(idx, piece)| (board.idx2pos(idx), piece))
+                .collect(),
+            side: position.side(),
+            bonus: position.bonus,
+            five,
+            over: position.is_over(),
+            moves: Vec::new(),
+        }
+    }
+
+    /// Replaces the game with this state, and the moves of the record with
+    /// its moves. Move numbers are not kept. The last five-in-a-row is put
+    /// back on the board, to blink and be removed again.
+    pub fn restore(&self, world: &World) {
+        // The five is recorded again as it blinks.
+        let moves = match self.moves.split_last() {
+            Some((Entry::FiveInARow(..), earlier)) if self.five.is_some() => earlier,
+            _ => &self.moves[..],
+        };
+        world.fetch_mut::<GameRecord>().replace_moves(moves);
+        world.fetch_mut::<History>().0.clear();
+        let mut board = Board::new(BOARD_HALF_WIDTH);
+        for (pos, piece) in self.stones.iter() {
+            board.put_piece(pos, *piece);
+        }
+        if let Some(five) = self.five {
+            for pos in five[1..].iter() {
+                board.put_piece(pos, self.side);
+            }
+            board.set_piece(&five[0], self.side);
+        }
+        *world.fetch_mut::<Board>() = board;
+        *world.fetch_mut::<Turn>() = self.side.turn();
+        world.fetch_mut::<BonusTurn>().0 = self.bonus;
+        world.fetch_mut::<GameOver>().0 = self.over;
+    }
+}
+
+/// Sent as one line of JSON each. The host, or the server, has the final say
+/// on the game: a guest only asks for moves, and plays them once the host
+/// confirms.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum Message {
+    /// The guest's first message. A spectator asks to `watch`, and a
+    /// player coming back after losing the connection gives its `token`.
+    Hello {
+        version: u32,
+        watch: bool,
+        token: Option<u64>,
+    },
+    /// The host's answer to `Hello`, with the side the guest plays, or
+    /// `None` for a spectator, and the token to come back with. Followed by
+    /// a `Sync`, after which a spectator is sent every `Moved`.
+    Welcome {
+        version: u32,
+        side: Option<Piece>,
+        token: Option<u64>,
+    },
+    /// A move the guest wants to make. In a bonus turn, a move on an
+    /// opponent stone captures it.
+    Move {
+        pos: Coord,
+    },
+    /// A move the host accepted, by any side.
+    Moved {
+        pos: Coord,
+    },
+    /// The move was not accepted. Followed by a `Sync`.
+    Rejected {
+        reason: String,
+    },
+    /// Asks the host for a `Sync`, when the guest lost track of the game.
+    Resync,
+    Sync(GameState),
+    /// A player lost the connection, and has a while to come back.
+    Away {
+        side: Piece,
+    },
+    /// A player who was away came back.
+    Back {
+        side: Piece,
+    },
+    /// A player stayed away too long, and lost the game.
+    Forfeited {
+        side: Piece,
+    },
+    /// A player's chat message. The host, or the server, sets the `side`
+    /// to that of whoever sent it, and passes it on to everyone else.
+    Chat {
+        side: Piece,
+        text: String,
+    },
+    /// A player's emote, passed on like `Chat`.
+    Emote {
+        side: Piece,
+        emote: Emote,
+    },
+    /// A player asks the other one for a draw or a takeback, which is
+    /// passed on like `Chat`.
+    Offer {
+        side: Piece,
+        offer: Offer,
+    },
+    /// The other player's answer to an `Offer`, passed on like `Chat`. The
+    /// host, or the server, then ends the game for an accepted draw, or takes
+    /// the moves back and follows with a `Sync`.
+    Answer {
+        side: Piece,
+        offer: Offer,
+        accepted: bool,
+    },
+    /// A player gave up, and lost the game.
+    Resigned {
+        side: Piece,
+    },
+    /// A player's clock ran out, by the host's clock, and the game is lost.
+    OutOfTime {
+        side: Piece,
+    },
+    /// Sent on a connection that has been quiet for a while, to show the
+    /// other end that it is still alive. Never passed on.
+    Ping,
+    /// Leaving for good.
+    Bye,
+}
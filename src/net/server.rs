@@ -0,0 +1,424 @@
+use log::{error, info, warn};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::connection::Connection;
+use super::discovery::Beacon;
+use super::protocol::{GameState, Message, PROTOCOL_VERSION};
+use super::{accept, beacon, NetConfig};
+use crate::ai::XorShift;
+use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+use crate::chat::truncate;
+use crate::cursor::Coord;
+use crate::offer::Offer;
+use crate::record::{Entry, GameRecord};
+use crate::rules::{IllegalMove, Position};
+use crate::Turn;
+
+/// How long the server sleeps when nothing happened.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct Client {
+    connection: Connection,
+    /// Whether it said `Hello`.
+    welcomed: bool,
+    /// The side it plays, or `None` for a spectator.
+    side: Option<Piece>,
+}
+
+/// A game kept without a window. The first two clients play black and
+/// white, the others and those who ask to watch are spectators. Every move
+/// is checked against the rules before it is sent to all clients, so a
+/// client cannot play for the other side or break the rules, whatever it
+/// sends.
+pub struct Server {
+    listener: TcpListener,
+    position: Position,
+    /// The five-in-a-row completed by the last move, if it did.
+    five: Option<[Coord; 5]>,
+    clients: Vec<Client>,
+    beacon: Option<Beacon>,
+    /// What each side's player comes back with, indexed by `Piece::idx`.
+    tokens: [Option<u64>; 2],
+    /// Sides whose player lost the connection, and since when.
+    away: Vec<(Piece, Instant)>,
+    /// Whether the game ended before the board filled up, by a forfeit, a
+    /// resignation or an agreed draw.
+    ended: bool,
+    /// An offer waiting for the other player's answer.
+    offer: Option<(Piece, Offer)>,
+    /// The moves played, as the game records them.
+    record: GameRecord,
+    /// The game before each move, with the length of the record, to take
+    /// moves back.
+    history: Vec<(Position, Option<[Coord; 5]>, usize)>,
+    grace: Duration,
+    rng: XorShift,
+}
+
+impl Server {
+    pub fn bind(addr: SocketAddr, name: String, config: &NetConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        info!("serving {:?} at {}", name, addr);
+        Ok(Self {
+            listener,
+            position: Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false),
+            five: None,
+            clients: Vec::new(),
+            beacon: beacon(name, addr),
+            tokens: [None; 2],
+            away: Vec::new(),
+            ended: false,
+            offer: None,
+            record: GameRecord::default(),
+            history: Vec::new(),
+            grace: config.reconnect_grace(),
+            rng: XorShift::from_time(),
+        })
+    }
+
+    /// Serves the game until the process is killed.
+    pub fn run(&mut self) {
+        loop {
+            self.announce();
+            self.check_away();
+            if !self.poll() {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn announce(&mut self) {
+        let count = |side: bool| {
+            self.clients
+                .iter()
+                .filter(|client| client.welcomed && client.side.is_some() == side)
+                .count()
+        };
+        let (players, spectators) = (count(true) + self.away.len(), count(false));
+        if let Some(beacon) = &mut self.beacon {
+            beacon.tick(players, spectators);
+        }
+    }
+
+    /// Forfeits the game for a player who stayed away too long.
+    fn check_away(&mut self) {
+        let grace = self.grace;
+        let gone: Vec<_> = self
+            .away
+            .iter()
+            .filter(|(_, since)| since.elapsed() > grace)
+            .map(|(side, _)| *side)
+            .collect();
+        self.away.retain(|(_, since)| since.elapsed() <= grace);
+        for side in gone {
+            self.tokens[side.idx()] = None;
+            if !self.is_over() {
+                info!("{:?} did not come back, and forfeits", side);
+                self.ended = true;
+                self.broadcast(Message::Forfeited { side });
+            }
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.ended || self.position.is_over()
+    }
+
+    fn state(&self) -> GameState {
+        GameState {
+            over: self.is_over(),
+            moves: self.record.entries.clone(),
+            ..GameState::from_position(&self.position, self.five)
+        }
+    }
+
+    /// Accepts new clients and answers their messages. Tells whether
+    /// anything happened.
+    fn poll(&mut self) -> bool {
+        let mut busy = false;
+        for connection in accept(&self.listener) {
+            busy = true;
+            info!("{} connected", connection.addr());
+            self.clients.push(Client {
+                connection,
+                welcomed: false,
+                side: None,
+            });
+        }
+
+        let mut idx = 0;
+        while idx < self.clients.len() {
+            match self.clients[idx].connection.poll() {
+                Ok(Some(message)) => {
+                    busy = true;
+                    self.message_received(idx, message);
+                }
+                Ok(None) => idx += 1,
+                Err(_) => {
+                    busy = true;
+                    let client = self.clients.remove(idx);
+                    info!("lost the connection to {}", client.connection.addr());
+                    if let Some(side) = client.side.filter(|_| !self.is_over()) {
+                        self.away.push((side, Instant::now()));
+                        self.broadcast(Message::Away { side });
+                    }
+                }
+            }
+        }
+        busy
+    }
+
+    fn message_received(&mut self, mut idx: usize, message: Message) {
+        match message {
+            Message::Hello {
+                version,
+                watch,
+                token,
+            } if !self.clients[idx].welcomed => {
+                if version != PROTOCOL_VERSION {
+                    let reason = format!(
+                        "protocol version {} expected, got {}",
+                        PROTOCOL_VERSION, version
+                    );
+                    self.send(idx, Message::Rejected { reason });
+                    self.clients.remove(idx);
+                    return;
+                }
+                let addr = self.clients[idx].connection.addr();
+                let taken = |piece: Piece| {
+                    self.clients.iter().any(|client| client.side == Some(piece))
+                        || self.away.iter().any(|(side, _)| *side == piece)
+                };
+                // A player who lost the connection gets their seat back, even
+                // before the server notices that the old connection is gone.
+                let back = [Piece::Black, Piece::White]
+                    .iter()
+                    .cloned()
+                    .find(|piece| token.is_some() && token == self.tokens[piece.idx()]);
+                let side = match back {
+                    Some(side) => {
+                        info!("{} is back with {:?}", addr, side);
+                        let stale = self
+                            .clients
+                            .iter()
+                            .position(|client| client.side == Some(side));
+                        if let Some(stale) = stale {
+                            let client = self.clients.remove(stale);
+                            info!(
+                                "dropped the stale connection to {}",
+                                client.connection.addr()
+                            );
+                            if stale < idx {
+                                idx -= 1;
+                            }
+                        }
+                        self.away.retain(|(away, _)| *away != side);
+                        self.broadcast(Message::Back { side });
+                        Some(side)
+                    }
+                    None if watch => None,
+                    None => {
+                        let side = [Piece::Black, Piece::White]
+                            .iter()
+                            .cloned()
+                            .find(|piece| !taken(*piece));
+                        if let Some(side) = side {
+                            self.tokens[side.idx()] = Some(self.rng.next());
+                        }
+                        side
+                    }
+                };
+                info!("{} plays {:?}", addr, side);
+                self.clients[idx].welcomed = true;
+                self.clients[idx].side = side;
+                self.send(
+                    idx,
+                    Message::Welcome {
+                        version: PROTOCOL_VERSION,
+                        side,
+                        token: side.and_then(|side| self.tokens[side.idx()]),
+                    },
+                );
+                self.send(idx, Message::Sync(self.state()));
+            }
+            Message::Move { pos } if self.clients[idx].welcomed => {
+                let before = (self.position.clone(), self.five, self.record.entries.len());
+                let played = if self.ended {
+                    Err(IllegalMove::GameOver)
+                } else if self.clients[idx].side != Some(self.position.side()) {
+                    Err(IllegalMove::WrongSide)
+                } else {
+                    self.position.play(&pos)
+                };
+                match played {
+                    Ok(played) => {
+                        self.history.push(before);
+                        self.record.push_move(&played);
+                        self.five = played.five;
+                        info!("{:?} plays at {}", self.clients[idx].side, pos);
+                        self.broadcast(Message::Moved { pos });
+                        if self.position.is_over() {
+                            info!("game over, five-in-a-rows {:?}", self.position.fives);
+                        }
+                    }
+                    Err(err) => {
+                        warn!(
+                            "rejected {} at {}: {}",
+                            self.clients[idx].connection.addr(),
+                            pos,
+                            err
+                        );
+                        self.send(
+                            idx,
+                            Message::Rejected {
+                                reason: err.to_string(),
+                            },
+                        );
+                        self.send(idx, Message::Sync(self.state()));
+                    }
+                }
+            }
+            Message::Resync => self.send(idx, Message::Sync(self.state())),
+            Message::Chat { text, .. } => {
+                if let Some(side) = self.clients[idx].side {
+                    let text = truncate(&text);
+                    self.relay(idx, Message::Chat { side, text });
+                }
+            }
+            Message::Emote { emote, .. } => {
+                if let Some(side) = self.clients[idx].side {
+                    self.relay(idx, Message::Emote { side, emote });
+                }
+            }
+            Message::Offer { offer, .. } => {
+                if let Some(side) = self.clients[idx].side {
+                    self.offer_received(idx, side, offer);
+                }
+            }
+            Message::Answer {
+                offer, accepted, ..
+            } => {
+                let side = match self.clients[idx].side {
+                    Some(side) if self.offer == Some((side.next(), offer)) => side,
+                    _ => {
+                        warn!(
+                            "{} answered an offer not made to it",
+                            self.clients[idx].connection.addr()
+                        );
+                        return;
+                    }
+                };
+                self.offer = None;
+                info!(
+                    "{:?} {} {}",
+                    side,
+                    if accepted { "accepts" } else { "declines" },
+                    offer
+                );
+                self.relay(
+                    idx,
+                    Message::Answer {
+                        side,
+                        offer,
+                        accepted,
+                    },
+                );
+                match offer {
+                    _ if !accepted => {}
+                    Offer::Draw => {
+                        self.ended = true;
+                        self.record.entries.push(Entry::Answered(side, offer, true));
+                    }
+                    Offer::Takeback => {
+                        self.take_back(side.next());
+                        self.broadcast(Message::Sync(self.state()));
+                    }
+                }
+            }
+            Message::Resigned { .. } => {
+                if let Some(side) = self.clients[idx].side.filter(|_| !self.is_over()) {
+                    info!("{:?} resigns", side);
+                    self.ended = true;
+                    self.record.entries.push(Entry::Resigned(side));
+                    self.relay(idx, Message::Resigned { side });
+                }
+            }
+            Message::Bye => {
+                let client = self.clients.remove(idx);
+                info!("{} left", client.connection.addr());
+            }
+            message => warn!(
+                "unexpected {:?} from {}",
+                message,
+                self.clients[idx].connection.addr()
+            ),
+        }
+    }
+
+    /// Passes a player's offer on, or declines it for the other player when
+    /// it cannot be made now.
+    fn offer_received(&mut self, idx: usize, side: Piece, offer: Offer) {
+        let moved = self
+            .history
+            .iter()
+            .any(|(position, ..)| position.side() == side);
+        if self.is_over() || self.offer.is_some() || (offer == Offer::Takeback && !moved) {
+            self.send(
+                idx,
+                Message::Answer {
+                    side: side.next(),
+                    offer,
+                    accepted: false,
+                },
+            );
+            return;
+        }
+        info!("{:?} asks for {}", side, offer);
+        self.offer = Some((side, offer));
+        self.relay(idx, Message::Offer { side, offer });
+    }
+
+    /// Takes back the last move of `side`, and every move after it.
+    fn take_back(&mut self, side: Piece) {
+        while let Some((position, five, moves)) = self.history.pop() {
+            let ours = position.side() == side;
+            self.position = position;
+            self.five = five;
+            self.record.entries.truncate(moves);
+            if ours {
+                break;
+            }
+        }
+        info!("took back to {:?}'s move", side);
+    }
+
+    fn send(&self, idx: usize, message: Message) {
+        let client = &self.clients[idx];
+        if let Err(err) = client.connection.send(&message) {
+            // The reader notices too, and the client is dropped on the next poll.
+            error!("unable to send to {}: {}", client.connection.addr(), err);
+        }
+    }
+
+    /// Sends a client's message to every other client.
+    fn relay(&self, from: usize, message: Message) {
+        for idx in 0..self.clients.len() {
+            if idx != from && self.clients[idx].welcomed {
+                self.send(idx, message.clone());
+            }
+        }
+    }
+
+    fn broadcast(&self, message: Message) {
+        for idx in 0..self.clients.len() {
+            if self.clients[idx].welcomed {
+                self.send(idx, message.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use amethyst::config::Config;
+use amethyst::Error;
+use std::fmt::{self, Display, Formatter, Write};
+use std::path::Path;
+
+use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+use crate::cursor::Coord;
+use crate::record::{Entry, GameRecord};
+use crate::rules::Position;
+use crate::Turn;
+
+/// A file format for game records, told by the file's extension.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    /// Our own `.ron`: the `GameRecord` itself, with every five-in-a-row
+    /// removed, every capture, and what the players said.
+    Ron,
+    /// Piskvork's `.psq`: a header line, then an `x,y,time` line per move,
+    /// counted from 1 at the top left. Only the moves are written: the
+    /// removals and captures follow from them under the endless rules.
+    Psq,
+    /// RenLib-style `.txt`: a move per line such as `12. black h8`, with
+    /// columns `a` to `o` from the left and rows 1 to 15 from the bottom. A
+    /// capture is written `xh8`, and a move completing a five-in-a-row `h8*`.
+    Text,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Ok(Format::Ron),
+            Some("psq") => Ok(Format::Psq),
+            Some("txt") => Ok(Format::Text),
+            _ => Err(Error::from_string(format!(
+                "unknown record format of {:?}, expected .ron, .psq or .txt",
+                path
+            ))),
+        }
+    }
+}
+
+/// Why a record could not be read back.
+#[derive(Debug, Clone)]
+pub struct ImportError {
+    /// The line of the file, or the move for `.ron`, counted from 1.
+    pub line: usize,
+    /// Whether `line` counts moves rather than lines.
+    pub by_move: bool,
+    pub reason: String,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let unit = if self.by_move { "move" } else { "line" };
+        write!(f, "{} {}: {}", unit, self.line, self.reason)
+    }
+}
+
+/// A move as written in a record.
+#[derive(Debug, Copy, Clone)]
+struct Written {
+    piece: Option<Piece>,
+    pos: Coord,
+    /// Whether it says the move captures, or completes a five-in-a-row,
+    /// when the format tells.
+    capture: Option<bool>,
+    five: Option<bool>,
+    line: usize,
+}
+
+/// Reads a record of any format, replaying its moves by the rules from an
+/// empty board, so that a record of an impossible game is refused.
+pub fn load(path: &Path) -> Result<GameRecord, Error> {
+    let format = Format::from_path(path)?;
+    let imported = match format {
+        Format::Ron => {
+            let record = GameRecord::load(path)?;
+            verify(&record).map(|_| record)
+        }
+        Format::Psq | Format::Text => {
+            let text = std::fs::read_to_string(path)?;
+            let moves = if format == Format::Psq {
+                parse_psq(&text)
+            } else {
+                parse_text(&text)
+            };
+            moves.and_then(|moves| replay(&moves))
+        }
+    };
+    imported.map_err(|err| Error::from_string(format!("{:?}: {}", path, err)))
+}
+
+/// Writes `record` in the format of `path`.
+pub fn save(record: &GameRecord, path: &Path) -> Result<(), Error> {
+    let text = match Format::from_path(path)? {
+        Format::Ron => return Ok(record.write(path)?),
+        Format::Psq => to_psq(record),
+        Format::Text => to_text(record),
+    };
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// The moves of a record, with whether each captured and completed a five.
+fn moves(record: &GameRecord) -> Vec<(Piece, Coord, bool, bool)> {
+    let mut moves = Vec::new();
+    let mut capture = false;
+    for entry in record.entries.iter() {
+        match entry {
+            Entry::Capture(..) => capture = true,
+            Entry::Place(piece, pos) => {
+                moves.push((*piece, *pos, capture, false));
+                capture = false;
+            }
+            Entry::FiveInARow(..) => {
+                if let Some(last) = moves.last_mut() {
+                    last.3 = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    moves
+}
+
+fn to_psq(record: &GameRecord) -> String {
+    let width = BOARD_HALF_WIDTH * 2 + 1;
+    let mut text = format!("Piskvorky {}x{}, 11:11, 0\n", width, width);
+    for (_, pos, ..) in moves(record) {
+        let x = pos.x + BOARD_HALF_WIDTH + 1;
+        let y = BOARD_HALF_WIDTH - pos.y + 1;
+        writeln!(text, "{},{},0", x, y).expect("unable to write to a string");
+    }
+    text
+}
+
+fn to_text(record: &GameRecord) -> String {
+    let width = BOARD_HALF_WIDTH * 2 + 1;
+    let mut text = format!("; endless five-in-a-row, {}x{}\n", width, width);
+    for (ply, (piece, pos, capture, five)) in moves(record).into_iter().enumerate() {
+        let side = match piece {
+            Piece::Black => "black",
+            Piece::White => "white",
+        };
+        writeln!(
+            text,
+            "{}. {} {}{}{}",
+            ply + 1,
+            side,
+            if capture { "x" } else { "" },
+            cell_name(&pos),
+            if five { "*" } else { "" }
+        )
+        .expect("unable to write to a string");
+    }
+    text
+}
+
+/// A cell such as `h8`, the center of the board.
+pub fn cell_name(pos: &Coord) -> String {
+    let column = (b'a' + (pos.x + BOARD_HALF_WIDTH) as u8) as char;
+    format!("{}{}", column, pos.y + BOARD_HALF_WIDTH + 1)
+}
+
+/// Reads a cell such as `h8`.
+pub fn parse_cell(text: &str) -> Option<Coord> {
+    let mut chars = text.chars();
+    let column = chars.next()?.to_ascii_lowercase();
+    if !column.is_ascii_lowercase() {
+        return None;
+    }
+    let row: i32 = chars.as_str().parse().ok()?;
+    let x = (column as u8 - b'a') as i32 - BOARD_HALF_WIDTH;
+    let y = row - 1 - BOARD_HALF_WIDTH;
+    if Board::new(BOARD_HALF_WIDTH).out_of_bound(x, y) {
+        return None;
+    }
+    Some(Coord::new_bounded(x, y))
+}
+
+fn parse_psq(text: &str) -> Result<Vec<Written>, ImportError> {
+    let mut moves = Vec::new();
+    // The header comes first, and whatever follows the moves, such as the
+    // players' names, is not ours to read.
+    for (idx, line) in text.lines().enumerate().skip(1) {
+        let fields: Vec<_> = line.trim().split(',').collect();
+        let cell = match fields.as_slice() {
+            [x, y] | [x, y, _] => x
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .zip(y.trim().parse::<i32>().ok()),
+            _ => None,
+        };
+        let (x, y) = match cell {
+            Some(cell) => cell,
+            None => break,
+        };
+        let pos = Coord::new_bounded(x - BOARD_HALF_WIDTH - 1, BOARD_HALF_WIDTH + 1 - y);
+        if Board::new(BOARD_HALF_WIDTH).out_of_bound(pos.x, pos.y) {
+            return Err(ImportError {
+                line: idx + 1,
+                by_move: false,
+                reason: format!("{},{} is out of the board", x, y),
+            });
+        }
+        moves.push(Written {
+            piece: None,
+            pos,
+            capture: None,
+            five: None,
+            line: idx + 1,
+        });
+    }
+    Ok(moves)
+}
+
+fn parse_text(text: &str) -> Result<Vec<Written>, ImportError> {
+    let mut moves = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+        let line = line.split(';').next().unwrap_or_default();
+        let mut piece = None;
+        for word in line.split_whitespace() {
+            if word.ends_with('.') && word[..word.len() - 1].parse::<u32>().is_ok() {
+                continue;
+            }
+            match word.to_ascii_lowercase().as_str() {
+                "black" | "b" => {
+                    piece = Some(Piece::Black);
+                    continue;
+                }
+                "white" | "w" => {
+                    piece = Some(Piece::White);
+                    continue;
+                }
+                _ => {}
+            }
+            let capture = word.starts_with('x');
+            let five = word.ends_with('*');
+            let cell = word.trim_start_matches('x').trim_end_matches('*');
+            let pos = parse_cell(cell).ok_or_else(|| ImportError {
+                line: line_number,
+                by_move: false,
+                reason: format!("{:?} is not a cell from a1 to o15", word),
+            })?;
+            moves.push(Written {
+                piece: piece.take(),
+                pos,
+                capture: Some(capture),
+                five: Some(five),
+                line: line_number,
+            });
+        }
+    }
+    Ok(moves)
+}
+
+/// Plays `moves` by the rules, and records what happened, checking it
+/// against what the record says.
+fn replay(moves: &[Written]) -> Result<GameRecord, ImportError> {
+    let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+    let mut record = GameRecord::default();
+    for written in moves.iter() {
+        let fail = |reason: String| ImportError {
+            line: written.line,
+            by_move: false,
+            reason: format!("{}: {}", cell_name(&written.pos), reason),
+        };
+        let side = position.side();
+        if written.piece.map_or(false, |piece| piece != side) {
+            return Err(fail(format!("it is {:?}'s turn", side)));
+        }
+        let played = position
+            .play(&written.pos)
+            .map_err(|err| fail(err.to_string()))?;
+        if written
+            .capture
+            .map_or(false, |capture| capture != played.captured.is_some())
+        {
+            return Err(fail("the capture does not match the rules".to_string()));
+        }
+        if written
+            .five
+            .map_or(false, |five| five != played.five.is_some())
+        {
+            return Err(fail(
+                "the five-in-a-row does not match the rules".to_string(),
+            ));
+        }
+        record.push_move(&played);
+    }
+    Ok(record)
+}
+
+/// Checks that the moves of a `.ron` record, its captures and its fives,
+/// are those the rules make of them.
+fn verify(record: &GameRecord) -> Result<(), ImportError> {
+    let written: Vec<_> = moves(record)
+        .into_iter()
+        .enumerate()
+        .map(|(ply, (piece, pos, capture, five))| Written {
+            piece: Some(piece),
+            pos,
+            capture: Some(capture),
+            five: Some(five),
+            line: ply + 1,
+        })
+        .collect();
+    let replayed = replay(&written).map_err(|err| ImportError {
+        by_move: true,
+        ..err
+    })?;
+    let expected = replayed.entries.iter().filter(|entry| entry.is_move());
+    if !record
+        .entries
+        .iter()
+        .filter(|entry| entry.is_move())
+        .eq(expected)
+    {
+        return Err(ImportError {
+            line: written.len(),
+            by_move: true,
+            reason: "the fives or captures do not match the moves".to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game in which black completes a five, captures a white stone in
+    /// the bonus turn, and white plays on.
+    fn game() -> GameRecord {
+        let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+        let mut record = GameRecord::default();
+        let moves = [
+            (0, 0),
+            (0, 5),
+            (1, 0),
+            (1, 5),
+            (2, 0),
+            (2, 5),
+            (3, 0),
+            (3, 5),
+            (4, 0),
+            (3, 5),
+            (6, 6),
+        ];
+        for (x, y) in moves.iter() {
+            let played = position
+                .play(&Coord::new_bounded(*x, *y))
+                .expect("unable to play");
+            record.push_move(&played);
+        }
+        assert!(record
+            .entries
+            .iter()
+            .any(|entry| matches!(entry, Entry::FiveInARow(..))));
+        assert!(record
+            .entries
+            .iter()
+            .any(|entry| matches!(entry, Entry::Capture(..))));
+        record
+    }
+
+    fn text(lines: &[&str]) -> Result<GameRecord, ImportError> {
+        parse_text(&lines.join("\n")).and_then(|moves| replay(&moves))
+    }
+
+    #[test]
+    fn cells_are_named_from_a1_to_o15() {
+        for (name, x, y) in [("a1", -7, -7), ("h8", 0, 0), ("o15", 7, 7)].iter() {
+            let pos = Coord::new_bounded(*x, *y);
+            assert_eq!(cell_name(&pos), *name);
+            assert_eq!(parse_cell(name), Some(pos));
+        }
+        for name in ["p1", "a16", "a0"].iter() {
+            assert_eq!(parse_cell(name), None);
+        }
+    }
+
+    #[test]
+    fn exported_games_import_the_same() {
+        let record = game();
+        for text in [to_psq(&record), to_text(&record)].iter() {
+            let moves = if text.starts_with("Piskvorky") {
+                parse_psq(text)
+            } else {
+                parse_text(text)
+            };
+            let imported = moves
+                .and_then(|moves| replay(&moves))
+                .expect("unable to import the exported game");
+            assert_eq!(imported.entries, record.entries);
+        }
+    }
+
+    #[test]
+    fn moves_against_the_record_or_the_rules_are_refused() {
+        assert!(text(&["1. black h8", "2. white h9"]).is_ok());
+        for (lines, line) in [
+            (&["1. black h8", "2. white h9*"][..], 2),
+            (&["1. black xh8"][..], 1),
+            (&["1. black h8", "2. white h8"][..], 2),
+            (&["1. white h8"][..], 1),
+        ]
+        .iter()
+        {
+            let err = text(lines).expect_err("an impossible game was imported");
+            assert_eq!(err.line, *line);
+        }
+    }
+
+    #[test]
+    fn ron_records_with_a_wrong_five_are_refused() {
+        let mut record = game();
+        assert!(verify(&record).is_ok());
+        for entry in record.entries.iter_mut() {
+            if let Entry::FiveInARow(_, five) = entry {
+                five[0] = Coord::new_bounded(5, 0);
+            }
+        }
+        let err = verify(&record).expect_err("a wrong five was imported");
+        assert!(err.to_string().starts_with("move "));
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+use crate::board::Piece;
+
+/// What one side may ask the other for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Offer {
+    /// Ending the game as a draw.
+    Draw,
+    /// Taking back the asking side's last move, and whatever was played
+    /// after it.
+    Takeback,
+}
+
+impl Display for Offer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Offer::Draw => write!(f, "a draw"),
+            Offer::Takeback => write!(f, "a takeback"),
+        }
+    }
+}
+
+/// A question for a player on this machine, answered with Y or N.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Prompt {
+    /// The given side asks the other one for something.
+    Offer(Piece, Offer),
+    /// Whether the given side really resigns.
+    Resign(Piece),
+}
+
+impl Display for Prompt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Prompt::Offer(side, offer) => write!(
+                f,
+                "{:?} asks for {}: Y to accept, N to decline",
+                side, offer
+            ),
+            Prompt::Resign(side) => write!(f, "resign as {:?}? Y to resign, N to play on", side),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::board::{Board, Piece, DIRECTIONS};
+use crate::cursor::Coord;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ThreatKind {
+    /// Three stones with a gap, e.g. `.X.XX.`, one move from an open four.
+    BrokenThree,
+    /// Three stones in a row, e.g. `..XXX.`, one move from an open four.
+    OpenThree,
+    /// Four stones one move from a five, with only one way to complete it.
+    Four,
+    /// Four stones in a row with both ends open, e.g. `.XXXX.`.
+    OpenFour,
+}
+
+/// A shape of one color along one line of the board.
+#[derive(Debug, Clone)]
+pub struct Threat {
+    pub kind: ThreatKind,
+    pub piece: Piece,
+    /// The stones making up the shape, as board indices.
+    pub stones: Vec<usize>,
+    /// Every empty cell of the pattern, including the open ends: where a four
+    /// is completed, or where a three is turned into a four.
+    pub gaps: Vec<usize>,
+}
+
+const EMPTY: u8 = b'.';
+const OURS: u8 = b'x';
+const BLOCKED: u8 = b'#';
+
+const PATTERNS: &[(&[u8], ThreatKind)] = &[
+    (b".xxxx.", ThreatKind::OpenFour),
+    (b"xxxx.", ThreatKind::Four),
+    (b".xxxx", ThreatKind::Four),
+    (b"x.xxx", ThreatKind::Four),
+    (b"xx.xx", ThreatKind::Four),
+    (b"xxx.x", ThreatKind::Four),
+    (b"..xxx.", ThreatKind::OpenThree),
+    (b".xxx..", ThreatKind::OpenThree),
+    (b".x.xx.", ThreatKind::BrokenThree),
+    (b".xx.x.", ThreatKind::BrokenThree),
+];
+
+/// Every line of the board, in the four directions, as board indices.
+pub fn lines(board: &Board) -> Vec<Vec<usize>> {
+    let half_width = board.half_width();
+    let mut lines = Vec::new();
+    for (dx, dy) in DIRECTIONS.iter() {
+        for y in -half_width..=half_width {
+            for x in -half_width..=half_width {
+                if !board.out_of_bound(x - dx, y - dy) {
+                    continue;
+                }
+                let mut line = Vec::new();
+                let (mut x, mut y) = (x, y);
+                while !board.out_of_bound(x, y) {
+                    line.push(board.pos2idx(&Coord::new_bounded(x, y)));
+                    x += dx;
+                    y += dy;
+                }
+                lines.push(line);
+            }
+        }
+    }
+    lines
+}
+
+/// Finds the threes and fours of both colors on the board. A group of stones
+/// matching several shapes is reported once, as the strongest one.
+pub fn find_threats(board: &Board) -> Vec<Threat> {
+    let mut found: HashMap<(Piece, Vec<usize>), Threat> = HashMap::new();
+    for line in lines(board) {
+        for piece in &[Piece::Black, Piece::White] {
+            scan_line(board, &line, *piece, &mut found);
+        }
+    }
+    found.into_iter().map(|(_, threat)| threat).collect()
+}
+
+fn scan_line(
+    board: &Board,
+    line: &[usize],
+    piece: Piece,
+    found: &mut HashMap<(Piece, Vec<usize>), Threat>,
+) {
+    let mut cells = Vec::with_capacity(line.len() + 2);
+    cells.push(BLOCKED);
+    for idx in line {
+        cells.push(match board.get_piece_by_idx(*idx) {
+            None => EMPTY,
+            Some(other) if *other == piece => OURS,
+            Some(_) => BLOCKED,
+        });
+    }
+    cells.push(BLOCKED);
+
+    for (pattern, kind) in PATTERNS {
+        for (start, window) in cells.windows(pattern.len()).enumerate() {
+            if window != *pattern {
+                continue;
+            }
+            // `cells` is padded by one on each side.
+            let idx_at = |offset: usize| line[start + offset - 1];
+            let stones: Vec<_> = (0..pattern.len())
+                .filter(|offset| pattern[*offset] == OURS)
+                .map(idx_at)
+                .collect();
+            let gaps: Vec<_> = (0..pattern.len())
+                .filter(|offset| pattern[*offset] == EMPTY)
+                .map(idx_at)
+                .collect();
+            let threat = found
+                .entry((piece, stones.clone()))
+                .or_insert_with(|| Threat {
+                    kind: *kind,
+                    piece,
+                    stones,
+                    gaps: Vec::new(),
+                });
+            if *kind > threat.kind {
+                threat.kind = *kind;
+                threat.gaps.clear();
+            }
+            if *kind == threat.kind {
+                for gap in gaps {
+                    if !threat.gaps.contains(&gap) {
+                        threat.gaps.push(gap);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BOARD_HALF_WIDTH;
+
+    /// A board with white stones at `white` and black ones at `black`.
+    fn board(white: &[(i32, i32)], black: &[(i32, i32)]) -> Board {
+        let mut board = Board::new(BOARD_HALF_WIDTH);
+        for (stones, piece) in [(white, Piece::White), (black, Piece::Black)].iter() {
+            for (x, y) in stones.iter() {
+                board.put_piece(&Coord::new_bounded(*x, *y), *piece);
+            }
+        }
+        board
+    }
+
+    /// The shapes of `piece` on the board, strongest first.
+    fn threats(board: &Board, piece: Piece) -> Vec<Threat> {
+        let mut threats: Vec<_> = find_threats(board)
+            .into_iter()
+            .filter(|threat| threat.piece == piece)
+            .collect();
+        threats.sort_by(|a, b| b.kind.cmp(&a.kind));
+        threats
+    }
+
+    fn indices(board: &Board, cells: &[(i32, i32)]) -> Vec<usize> {
+        let mut indices: Vec<_> = cells
+            .iter()
+            .map(|(x, y)| board.pos2idx(&Coord::new_bounded(*x, *y)))
+            .collect();
+        indices.sort();
+        indices
+    }
+
+    fn gaps(threat: &Threat) -> Vec<usize> {
+        let mut gaps = threat.gaps.clone();
+        gaps.sort();
+        gaps
+    }
+
+    const FOUR: [(i32, i32); 4] = [(0, 0), (1, 0), (2, 0), (3, 0)];
+    const THREE: [(i32, i32); 3] = [(0, 0), (1, 0), (2, 0)];
+
+    #[test]
+    fn open_fours_are_found_for_both_colors() {
+        for (piece, board) in [
+            (Piece::White, board(&FOUR, &[])),
+            (Piece::Black, board(&[], &FOUR)),
+        ]
+        .iter()
+        {
+            let threats = threats(board, *piece);
+            assert_eq!(threats[0].kind, ThreatKind::OpenFour);
+            assert_eq!(threats[0].stones, indices(board, &FOUR));
+            assert_eq!(gaps(&threats[0]), indices(board, &[(-1, 0), (4, 0)]));
+        }
+    }
+
+    #[test]
+    fn fours_blocked_on_one_end_are_found() {
+        let board = board(&FOUR, &[(-1, 0)]);
+        let threats = threats(&board, Piece::White);
+        assert_eq!(threats[0].kind, ThreatKind::Four);
+        assert_eq!(gaps(&threats[0]), indices(&board, &[(4, 0)]));
+    }
+
+    #[test]
+    fn fours_with_a_gap_are_found() {
+        let board = board(&[(0, 0), (1, 0), (3, 0), (4, 0)], &[]);
+        let threats = threats(&board, Piece::White);
+        assert_eq!(threats[0].kind, ThreatKind::Four);
+        assert_eq!(gaps(&threats[0]), indices(&board, &[(2, 0)]));
+    }
+
+    #[test]
+    fn open_threes_are_found() {
+        let board = board(&THREE, &[]);
+        let threats = threats(&board, Piece::White);
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].kind, ThreatKind::OpenThree);
+        assert_eq!(threats[0].stones, indices(&board, &THREE));
+    }
+
+    #[test]
+    fn broken_threes_are_found() {
+        let board = board(&[(0, 0), (2, 0), (3, 0)], &[]);
+        let threats = threats(&board, Piece::White);
+        assert_eq!(threats.len(), 1);
+        assert_eq!(threats[0].kind, ThreatKind::BrokenThree);
+        assert_eq!(
+            gaps(&threats[0]),
+            indices(&board, &[(-1, 0), (1, 0), (4, 0)])
+        );
+    }
+
+    #[test]
+    fn threes_blocked_on_both_ends_are_not_found() {
+        let board = board(&THREE, &[(-1, 0), (3, 0)]);
+        assert!(threats(&board, Piece::White).is_empty());
+    }
+}
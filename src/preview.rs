@@ -0,0 +1,103 @@
+use amethyst::ecs::*;
+use std::collections::HashMap;
+
+use crate::board::Board;
+use crate::cursor::{Coord, Cursor};
+use crate::highlight::{Highlight, Highlights};
+use crate::pattern::{find_threats, ThreatKind};
+use crate::{BonusTurn, Turn};
+
+/// Highlights the capturable stones in a bonus turn, and what the move under
+/// the cursor would do: the five-in-a-row it completes, or otherwise the
+/// cells where the opponent could complete one in reply.
+pub struct PreviewSystem;
+
+impl<'a> System<'a> for PreviewSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, Board>,
+        ReadExpect<'a, Turn>,
+        ReadExpect<'a, BonusTurn>,
+        ReadStorage<'a, Coord>,
+        ReadStorage<'a, Cursor>,
+        Write<'a, Highlights>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (cursor_entity, board, turn, bonus_turn, coords, cursors, mut highlights) = data;
+        let piece = turn.piece();
+
+        if bonus_turn.0 {
+            let capturable = board
+                .pieces()
+                .filter(|(_, other)| *other != piece)
+                .map(|(idx, _)| idx);
+            highlights.set(Highlight::Capturable, capturable);
+        } else {
+            highlights.clear(Highlight::Capturable);
+        }
+
+        let hovered = match (coords.get(*cursor_entity), cursors.get(*cursor_entity)) {
+            (Some(pos), Some(cursor)) if cursor.show => Some(*pos),
+            _ => None,
+        };
+        let hovered = hovered.filter(|pos| match board.get_piece(pos) {
+            Some(other) => bonus_turn.0 && *other != piece,
+            None => true,
+        });
+        highlights.clear(Highlight::FiveInARow);
+        highlights.clear(Highlight::Threat);
+        if let Some(pos) = hovered {
+            match board.five_if_placed(&pos, piece) {
+                Some(fir) => highlights.set(
+                    Highlight::FiveInARow,
+                    fir.iter().map(|pos| board.pos2idx(pos)),
+                ),
+                None => highlights.set(
+                    Highlight::Threat,
+                    board
+                        .threats_after(&pos, piece)
+                        .iter()
+                        .map(|pos| board.pos2idx(pos)),
+                ),
+            }
+        }
+    }
+}
+
+/// Whether the threes and fours of both colors are marked on the board.
+#[derive(Default)]
+pub struct ShowThreats(pub bool);
+
+pub struct ThreatOverlaySystem;
+
+impl<'a> System<'a> for ThreatOverlaySystem {
+    type SystemData = (
+        ReadExpect<'a, Board>,
+        Read<'a, ShowThreats>,
+        Write<'a, Highlights>,
+    );
+
+    fn run(&mut self, (board, show_threats, mut highlights): Self::SystemData) {
+        let mut overlay: HashMap<Highlight, Vec<usize>> = HashMap::new();
+        for kind in &[
+            ThreatKind::BrokenThree,
+            ThreatKind::OpenThree,
+            ThreatKind::Four,
+            ThreatKind::OpenFour,
+        ] {
+            overlay.insert(Highlight::from(*kind), Vec::new());
+        }
+        if show_threats.0 {
+            for threat in find_threats(&board) {
+                overlay
+                    .entry(Highlight::from(threat.kind))
+                    .or_default()
+                    .extend(threat.stones);
+            }
+        }
+        for (highlight, cells) in overlay {
+            highlights.set(highlight, cells);
+        }
+    }
+}
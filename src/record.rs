@@ -0,0 +1,202 @@
+use amethyst::ecs::*;
+use amethyst::shrev::{EventChannel, ReaderId};
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+use crate::chat::Emote;
+use crate::cursor::Coord;
+use crate::event::GameEvent;
+use crate::offer::Offer;
+use crate::rules::{IllegalMove, Played, Position};
+use crate::Turn;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Entry {
+    Place(Piece, Coord),
+    /// An opponent stone of the given color captured in a bonus turn.
+    Capture(Piece, Coord),
+    FiveInARow(Piece, [Coord; 5]),
+    /// A hint shown to the given side.
+    Hint(Piece, Coord),
+    /// A chat message of the given side, in a networked game.
+    Chat(Piece, String),
+    Emote(Piece, Emote),
+    /// The given side asked the other one for a draw or a takeback.
+    Offered(Piece, Offer),
+    /// The given side accepted the offer, or declined it.
+    Answered(Piece, Offer, bool),
+    Resigned(Piece),
+    /// The given side's clock ran out.
+    OutOfTime(Piece),
+}
+
+impl Entry {
+    /// Whether the entry is something the players said rather than did on
+    /// the board, which stays in the record when moves are taken back.
+    pub fn is_talk(&self) -> bool {
+        matches!(
+            self,
+            Entry::Chat(..) | Entry::Emote(..) | Entry::Offered(..) | Entry::Answered(..)
+        )
+    }
+
+    /// Whether the entry is part of a move on the board.
+    pub fn is_move(&self) -> bool {
+        matches!(
+            self,
+            Entry::Place(..) | Entry::Capture(..) | Entry::FiveInARow(..)
+        )
+    }
+
+    /// Whether the entry ends the game before the board is full.
+    pub fn ends_game(&self) -> bool {
+        matches!(
+            self,
+            Entry::Resigned(..) | Entry::OutOfTime(..) | Entry::Answered(_, Offer::Draw, true)
+        )
+    }
+}
+
+/// Everything that happened in the game, in order.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct GameRecord {
+    pub entries: Vec<Entry>,
+}
+
+impl GameRecord {
+    /// Records a move played by the rules, as the game records it: the
+    /// capture, the stone placed, then the five-in-a-row it completed.
+    pub fn push_move(&mut self, played: &Played) {
+        if let Some(captured) = played.captured {
+            self.entries.push(Entry::Capture(captured, played.pos));
+        }
+        self.entries.push(Entry::Place(played.piece, played.pos));
+        if let Some(five) = played.five {
+            self.entries.push(Entry::FiveInARow(played.piece, five));
+        }
+    }
+
+    /// Replaces the moves of the record, and how the game ended, with
+    /// `moves`. Those that agree are kept where they are among the other
+    /// entries, which are all kept, and the rest of `moves` is added at the
+    /// end.
+    pub fn replace_moves(&mut self, moves: &[Entry]) {
+        let mut moves = moves.iter().peekable();
+        let mut agree = true;
+        self.entries.retain(|entry| {
+            if !entry.is_move() && !entry.ends_game() {
+                return true;
+            }
+            agree = agree && moves.peek() == Some(&entry);
+            if agree {
+                moves.next();
+            }
+            agree
+        });
+        self.entries.extend(moves.cloned());
+    }
+
+    pub fn hints(&self, piece: Piece) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Hint(side, _) if *side == piece))
+            .count()
+    }
+
+    /// Every move of the game with the position it was played in, replayed
+    /// by the rules from an empty board. A capture and the stone placed on
+    /// the captured cell count as one move.
+    pub fn moves(&self) -> Result<Vec<(Position, Coord)>, IllegalMove> {
+        let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+        let mut moves = Vec::new();
+        for entry in self.entries.iter() {
+            if let Entry::Place(piece, pos) = entry {
+                if *piece != position.side() {
+                    return Err(IllegalMove::WrongSide);
+                }
+                moves.push((position.clone(), *pos));
+                position.play(pos)?;
+            }
+        }
+        Ok(moves)
+    }
+}
+
+#[derive(Default)]
+pub struct RecordSystem {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for RecordSystem {
+    type SystemData = (Read<'a, EventChannel<GameEvent>>, Write<'a, GameRecord>);
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (events, mut record): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("record system is not set up");
+        for event in events.read(reader) {
+            let entry = match *event {
+                GameEvent::PiecePlaced(piece, pos) => Entry::Place(piece, pos),
+                GameEvent::Captured(piece, pos) => Entry::Capture(piece, pos),
+                GameEvent::FiveInARow(piece, fir) => Entry::FiveInARow(piece, fir),
+                GameEvent::HintShown(piece, pos) => Entry::Hint(piece, pos),
+                GameEvent::InvalidClick(..)
+                | GameEvent::FiveRemoved(..)
+                | GameEvent::GameOver
+                | GameEvent::Undone
+                | GameEvent::NewGame
+                | GameEvent::Restored => continue,
+            };
+            record.entries.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaced_moves_keep_what_was_said() {
+        let pos = |x, y| Coord::new_bounded(x, y);
+        let mut record = GameRecord {
+            entries: vec![
+                Entry::Place(Piece::Black, pos(0, 0)),
+                Entry::Chat(Piece::White, "hi".to_string()),
+                Entry::Place(Piece::White, pos(1, 0)),
+                Entry::Hint(Piece::Black, pos(2, 2)),
+                Entry::Place(Piece::Black, pos(3, 3)),
+                Entry::Emote(Piece::White, Emote::GoodMove),
+            ],
+        };
+        // The host did not take the last move, took two more, and resigned.
+        let moves = vec![
+            Entry::Place(Piece::Black, pos(0, 0)),
+            Entry::Place(Piece::White, pos(1, 0)),
+            Entry::Place(Piece::Black, pos(2, 2)),
+            Entry::Place(Piece::White, pos(4, 4)),
+            Entry::Resigned(Piece::Black),
+        ];
+        record.replace_moves(&moves);
+        assert_eq!(
+            record.entries,
+            vec![
+                Entry::Place(Piece::Black, pos(0, 0)),
+                Entry::Chat(Piece::White, "hi".to_string()),
+                Entry::Place(Piece::White, pos(1, 0)),
+                Entry::Hint(Piece::Black, pos(2, 2)),
+                Entry::Emote(Piece::White, Emote::GoodMove),
+                Entry::Place(Piece::Black, pos(2, 2)),
+                Entry::Place(Piece::White, pos(4, 4)),
+                Entry::Resigned(Piece::Black),
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::{Display, Formatter};
+
+use crate::board::{Board, Piece};
+use crate::cursor::Coord;
+use crate::Turn;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IllegalMove {
+    OutOfBound,
+    Occupied,
+    /// Only an opponent stone can be captured in a bonus turn.
+    OwnStone,
+    /// The stone is not of the side to move.
+    WrongSide,
+    GameOver,
+}
+
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IllegalMove::OutOfBound => write!(f, "the cell is out of the board"),
+            IllegalMove::Occupied => write!(f, "the cell is occupied"),
+            IllegalMove::OwnStone => write!(f, "cannot capture one's own stone"),
+            IllegalMove::WrongSide => write!(f, "it is the other side's turn"),
+            IllegalMove::GameOver => write!(f, "the game is over"),
+        }
+    }
+}
+
+/// What a move did, with enough to take it back.
+#[derive(Debug, Clone)]
+pub struct Played {
+    pub pos: Coord,
+    pub piece: Piece,
+    pub captured: Option<Piece>,
+    pub five: Option<[Coord; 5]>,
+    turn: Turn,
+    bonus: bool,
+}
+
+/// A game state with the endless rules applied at once: a completed
+/// five-in-a-row is removed right away and the same side gets a bonus turn,
+/// in which it may also capture an opponent stone.
+#[derive(Clone)]
+pub struct Position {
+    pub board: Board,
+    pub turn: Turn,
+    pub bonus: bool,
+    /// Five-in-a-rows completed by each side, indexed by `Piece::idx`.
+    pub fives: [u32; 2],
+}
+
+impl Position {
+    pub fn new(board: Board, turn: Turn, bonus: bool) -> Self {
+        Self {
+            board,
+            turn,
+            bonus,
+            fives: [0; 2],
+        }
+    }
+    pub fn side(&self) -> Piece {
+        self.turn.piece()
+    }
+    pub fn is_over(&self) -> bool {
+        self.board.is_full()
+    }
+
+    /// Checks whether the side to move may play at `pos`, and returns the
+    /// opponent stone it would capture, if any.
+    pub fn check(&self, pos: &Coord) -> Result<Option<Piece>, IllegalMove> {
+        if self.is_over() {
+            return Err(IllegalMove::GameOver);
+        }
+        if pos.out_of_bound || self.board.out_of_bound(pos.x, pos.y) {
+            return Err(IllegalMove::OutOfBound);
+        }
+        match self.board.get_piece(pos) {
+            None => Ok(None),
+            Some(_) if !self.bonus => Err(IllegalMove::Occupied),
+            Some(piece) if *piece == self.side() => Err(IllegalMove::OwnStone),
+            Some(piece) => Ok(Some(*piece)),
+        }
+    }
+
+    pub fn play(&mut self, pos: &Coord) -> Result<Played, IllegalMove> {
+        let captured = self.check(pos)?;
+        let piece = self.side();
+        let (turn, bonus) = (self.turn, self.bonus);
+        if captured.is_some() {
+            self.board.remove_piece(pos);
+        }
+        let five = self.board.place(pos, piece);
+        match five {
+            Some(line) => {
+                for pos in line.iter() {
+                    self.board.remove_piece(pos);
+                }
+                self.fives[piece.idx()] += 1;
+                self.bonus = true;
+            }
+            None => {
+                self.bonus = false;
+                self.turn = self.turn.next();
+            }
+        }
+        Ok(Played {
+            pos: *pos,
+            piece,
+            captured,
+            five,
+            turn,
+            bonus,
+        })
+    }
+
+    pub fn undo(&mut self, played: &Played) {
+        if let Some(line) = played.five {
+            for pos in line.iter() {
+                self.board.put_piece(pos, played.piece);
+            }
+            self.fives[played.piece.idx()] -= 1;
+        }
+        self.board.remove_piece(&played.pos);
+        if let Some(captured) = played.captured {
+            self.board.put_piece(&played.pos, captured);
+        }
+        self.turn = played.turn;
+        self.bonus = played.bonus;
+    }
+
+    /// All legal moves: every empty cell, and in a bonus turn every opponent
+    /// stone as well.
+    pub fn legal_moves(&self) -> Vec<Coord> {
+        if self.is_over() {
+            return Vec::new();
+        }
+        (0..self.board.size())
+            .map(|idx| self.board.idx2pos(idx))
+            .filter(|pos| self.check(pos).is_ok())
+            .collect()
+    }
+}
@@ -0,0 +1,390 @@
+use amethyst::Error;
+use log::{info, warn};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ai::{candidate_moves, run_search, AiConfig, Book, Engine, XorShift};
+use crate::board::{Board, Piece, BOARD_HALF_WIDTH};
+use crate::cursor::Coord;
+use crate::rules::Position;
+use crate::Turn;
+
+/// Opening moves are picked among this many of the most promising.
+const OPENING_BREADTH: usize = 4;
+/// Scores this close to 0 or 1 are treated as such when computing Elo.
+const SCORE_EPSILON: f64 = 0.001;
+/// How long a brain has to quit once told to.
+const BRAIN_QUIT_WAIT: Duration = Duration::from_secs(2);
+const BRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Something that picks moves: an engine in this process, or an external
+/// program speaking the brain protocol.
+pub trait Player {
+    fn name(&self) -> &str;
+    fn play(&mut self, position: &Position) -> amethyst::Result<Coord>;
+}
+
+pub struct EnginePlayer {
+    name: String,
+    config: AiConfig,
+    book: Book,
+    engine: Engine,
+}
+
+impl EnginePlayer {
+    pub fn new(name: String, config: AiConfig, book: Book) -> Self {
+        Self {
+            name,
+            engine: Engine::new(config.clone()),
+            config,
+            book,
+        }
+    }
+}
+
+impl Player for EnginePlayer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn play(&mut self, position: &Position) -> amethyst::Result<Coord> {
+        if let Some(pos) = self.book.probe(position) {
+            return Ok(pos);
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = self.config.clone();
+        run_search(&mut self.engine, position.clone(), config, stop, |_| {})
+            .best
+            .ok_or_else(|| Error::from_string(format!("{} found no move", self.name)))
+    }
+}
+
+/// An external program, such as another build of this game run with the
+/// `brain` command. It is sent one line per move to make,
+///
+/// `go <side>[+] <black fives> <white fives> [<b|w><x>,<y> ...]`
+///
+/// where `+` marks a bonus turn, and answers with `move <x>,<y>`. It is sent
+/// `quit` when the match is over.
+pub struct Brain {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Brain {
+    /// Starts `command`, split on whitespace into the program and its arguments.
+    pub fn spawn(command: &str) -> amethyst::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| Error::from_string("empty brain command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("brain stdin is not piped");
+        let stdout = child.stdout.take().expect("brain stdout is not piped");
+        Ok(Self {
+            name: command.to_string(),
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+}
+
+impl Player for Brain {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn play(&mut self, position: &Position) -> amethyst::Result<Coord> {
+        writeln!(self.stdin, "go {}", encode(position))?;
+        self.stdin.flush()?;
+        let mut line = String::new();
+        self.stdout.read_line(&mut line)?;
+        let pos = line
+            .trim()
+            .strip_prefix("move ")
+            .and_then(parse_coord)
+            .ok_or_else(|| {
+                Error::from_string(format!("{} answered {:?}", self.name, line.trim()))
+            })?;
+        Ok(pos)
+    }
+}
+
+impl Drop for Brain {
+    /// Asks the program to quit, and kills it if it does not in time.
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.stdin.flush();
+        let deadline = Instant::now() + BRAIN_QUIT_WAIT;
+        while Instant::now() < deadline {
+            match self.child.try_wait() {
+                Ok(Some(_)) | Err(_) => return,
+                Ok(None) => thread::sleep(BRAIN_POLL_INTERVAL),
+            }
+        }
+        warn!("{} did not quit, killing it", self.name);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The position as sent to a brain.
+pub fn encode(position: &Position) -> String {
+    let board = &position.board;
+    let mut stones: Vec<_> = board.pieces().collect();
+    stones.sort_by_key(|(idx, _)| *idx);
+    let mut line = format!(
+        "{}{} {} {}",
+        piece_char(position.side()),
+        if position.bonus { "+" } else { "" },
+        position.fives[0],
+        position.fives[1]
+    );
+    for (idx, piece) in stones {
+        let pos = board.idx2pos(idx);
+        line.push_str(&format!(" {}{},{}", piece_char(piece), pos.x, pos.y));
+    }
+    line
+}
+
+/// Reads a position sent to a brain, without the leading `go`.
+pub fn decode(line: &str) -> Option<Position> {
+    let mut words = line.split_whitespace();
+    let side = words.next()?;
+    let turn = match side.trim_end_matches('+') {
+        "b" => Turn::Player,
+        "w" => Turn::Computer,
+        _ => return None,
+    };
+    let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), turn, side.ends_with('+'));
+    position.fives = [words.next()?.parse().ok()?, words.next()?.parse().ok()?];
+    for word in words {
+        let piece = match word.chars().next()? {
+            'b' => Piece::Black,
+            'w' => Piece::White,
+            _ => return None,
+        };
+        let pos = parse_coord(&word[1..])?;
+        if position.board.out_of_bound(pos.x, pos.y) {
+            return None;
+        }
+        position.board.put_piece(&pos, piece);
+    }
+    Some(position)
+}
+
+/// Reads a cell written as `x,y`.
+pub fn parse_coord(text: &str) -> Option<Coord> {
+    let mut parts = text.trim().splitn(2, ',');
+    let x = parts.next()?.trim().parse().ok()?;
+    let y = parts.next()?.trim().parse().ok()?;
+    Some(Coord::new_bounded(x, y))
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Black => 'b',
+        Piece::White => 'w',
+    }
+}
+
+/// A few moves picked at random among the promising ones, so that games
+/// between the same deterministic engines differ.
+pub fn random_opening(rng: &mut XorShift, plies: usize) -> Vec<Coord> {
+    let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+    let mut opening = Vec::new();
+    for _ in 0..plies {
+        let moves = candidate_moves(&position, OPENING_BREADTH);
+        if moves.is_empty() {
+            break;
+        }
+        let pos = moves[rng.below(moves.len())];
+        if position.play(&pos).is_err() {
+            break;
+        }
+        opening.push(pos);
+    }
+    opening
+}
+
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    /// Five-in-a-rows completed by each side, indexed by `Piece::idx`.
+    pub fives: [u32; 2],
+    pub moves: u32,
+    /// The side that lost by playing an illegal move or failing to answer.
+    pub forfeit: Option<Piece>,
+}
+
+impl GameResult {
+    /// `None` for a draw.
+    pub fn winner(&self) -> Option<Piece> {
+        if let Some(piece) = self.forfeit {
+            return Some(piece.next());
+        }
+        if self.fives[0] > self.fives[1] {
+            Some(Piece::Black)
+        } else if self.fives[1] > self.fives[0] {
+            Some(Piece::White)
+        } else {
+            None
+        }
+    }
+}
+
+/// Plays one game from `opening` until the board is full or `max_moves`
+/// moves are made, `players[0]` with black. A bonus turn counts as a move.
+pub fn play_game(
+    players: &mut [&mut dyn Player; 2],
+    opening: &[Coord],
+    max_moves: u32,
+) -> GameResult {
+    let mut position = Position::new(Board::new(BOARD_HALF_WIDTH), Turn::Player, false);
+    let mut moves = 0;
+    let mut forfeit = None;
+    while moves < max_moves && !position.is_over() {
+        let side = position.side();
+        let pos = match opening.get(moves as usize) {
+            Some(pos) => Ok(*pos),
+            None => players[side.idx()].play(&position),
+        };
+        let played = pos.and_then(|pos| {
+            position
+                .play(&pos)
+                .map_err(|err| Error::from_string(format!("{} at {}", err, pos)))
+        });
+        if let Err(err) = played {
+            warn!("{} forfeits: {}", players[side.idx()].name(), err);
+            forfeit = Some(side);
+            break;
+        }
+        moves += 1;
+    }
+    GameResult {
+        fives: position.fives,
+        moves,
+        forfeit,
+    }
+}
+
+/// Results of a match, from the first player's point of view.
+#[derive(Debug, Clone, Default)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// Five-in-a-rows completed by the first and the second player.
+    pub fives: [u32; 2],
+    pub moves: u32,
+}
+
+impl MatchStats {
+    pub fn add(&mut self, result: &GameResult, first_is_black: bool) {
+        let first = if first_is_black {
+            Piece::Black
+        } else {
+            Piece::White
+        };
+        match result.winner() {
+            Some(winner) if winner == first => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+        self.fives[0] += result.fives[first.idx()];
+        self.fives[1] += result.fives[first.next().idx()];
+        self.moves += result.moves;
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// The first player's Elo advantage, with the 95% confidence interval.
+    pub fn elo(&self) -> (f64, f64, f64) {
+        let games = self.games().max(1) as f64;
+        let score = (self.wins as f64 + self.draws as f64 / 2.0) / games;
+        let deviation = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games;
+        let margin = 1.96 * (deviation / games).sqrt();
+        (elo(score), elo(score - margin), elo(score + margin))
+    }
+}
+
+impl Display for MatchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let games = self.games().max(1) as f64;
+        let (elo, low, high) = self.elo();
+        writeln!(
+            f,
+            "games {}: +{} -{} ={}",
+            self.games(),
+            self.wins,
+            self.losses,
+            self.draws
+        )?;
+        writeln!(
+            f,
+            "five-in-a-rows {} : {}, {:.1} moves per game",
+            self.fives[0],
+            self.fives[1],
+            self.moves as f64 / games
+        )?;
+        write!(f, "elo {:+.0} (95% {:+.0} to {:+.0})", elo, low, high)
+    }
+}
+
+fn elo(score: f64) -> f64 {
+    let score = score.max(SCORE_EPSILON).min(1.0 - SCORE_EPSILON);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Plays `games` games between the two players, switching colors after each
+/// game so that every random opening is played from both sides.
+pub fn run_match(
+    first: &mut dyn Player,
+    second: &mut dyn Player,
+    games: u32,
+    opening_plies: usize,
+    max_moves: u32,
+) -> MatchStats {
+    let mut rng = XorShift::from_time();
+    let mut stats = MatchStats::default();
+    let mut opening = Vec::new();
+    for game in 0..games {
+        let first_is_black = game % 2 == 0;
+        if first_is_black {
+            opening = random_opening(&mut rng, opening_plies);
+        }
+        let result = if first_is_black {
+            play_game(&mut [&mut *first, &mut *second], &opening, max_moves)
+        } else {
+            play_game(&mut [&mut *second, &mut *first], &opening, max_moves)
+        };
+        stats.add(&result, first_is_black);
+        info!(
+            "game {}: {} five-in-a-rows {:?} in {} moves, winner {:?}",
+            game + 1,
+            if first_is_black {
+                "first black"
+            } else {
+                "first white"
+            },
+            result.fives,
+            result.moves,
+            result.winner()
+        );
+    }
+    stats
+}
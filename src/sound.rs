@@ -0,0 +1,139 @@
+use amethyst::assets::{AssetStorage, Loader};
+use amethyst::audio::output::{init_output, Output};
+use amethyst::audio::{AudioSink, OggFormat, Source, SourceHandle, WavFormat};
+use amethyst::ecs::*;
+use amethyst::shrev::{EventChannel, ReaderId};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::iter::Cycle;
+use std::vec::IntoIter;
+
+use crate::event::GameEvent;
+
+const PLACE_SOUND: &str = "audio/place.wav";
+const FIVE_SOUND: &str = "audio/five.wav";
+const CAPTURE_SOUND: &str = "audio/capture.wav";
+const INVALID_SOUND: &str = "audio/invalid.wav";
+const GAME_OVER_SOUND: &str = "audio/game_over.wav";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub effects_volume: f32,
+    pub music_volume: f32,
+    /// Tracks played in a loop, relative to the assets directory.
+    pub music: Vec<String>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            effects_volume: 1.0,
+            music_volume: 0.25,
+            music: Vec::new(),
+        }
+    }
+}
+
+pub struct Sounds {
+    pub place: SourceHandle,
+    pub five: SourceHandle,
+    pub capture: SourceHandle,
+    pub invalid: SourceHandle,
+    pub game_over: SourceHandle,
+}
+
+pub struct Music {
+    pub music: Cycle<IntoIter<SourceHandle>>,
+}
+
+fn load_source(loader: &Loader, storage: &AssetStorage<Source>, file: &str) -> SourceHandle {
+    if file.ends_with(".ogg") {
+        loader.load(file, OggFormat, (), storage)
+    } else {
+        loader.load(file, WavFormat, (), storage)
+    }
+}
+
+/// Loads the sound effects and music. Without an audio device there is no
+/// `Output` or `AudioSink` in the world, and nothing is played.
+pub fn initialize_audio(world: &mut World) {
+    init_output(world);
+    let (sounds, music) = {
+        let config = world.read_resource::<AudioConfig>();
+        let loader = world.read_resource::<Loader>();
+        let storage = world.read_resource::<AssetStorage<Source>>();
+        let sounds = Sounds {
+            place: load_source(&loader, &storage, PLACE_SOUND),
+            five: load_source(&loader, &storage, FIVE_SOUND),
+            capture: load_source(&loader, &storage, CAPTURE_SOUND),
+            invalid: load_source(&loader, &storage, INVALID_SOUND),
+            game_over: load_source(&loader, &storage, GAME_OVER_SOUND),
+        };
+        let music = config
+            .music
+            .iter()
+            .map(|file| load_source(&loader, &storage, file))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .cycle();
+        (sounds, Music { music })
+    };
+    let music_volume = world.read_resource::<AudioConfig>().music_volume;
+    match world.try_fetch_mut::<AudioSink>() {
+        Some(mut sink) => sink.set_volume(music_volume),
+        None => info!("no audio device found, sound is disabled"),
+    }
+    world.insert(sounds);
+    world.insert(music);
+}
+
+#[derive(Default)]
+pub struct SoundSystem {
+    reader: Option<ReaderId<GameEvent>>,
+}
+
+impl<'a> System<'a> for SoundSystem {
+    type SystemData = (
+        Read<'a, AssetStorage<Source>>,
+        Read<'a, EventChannel<GameEvent>>,
+        ReadExpect<'a, AudioConfig>,
+        Option<Read<'a, Sounds>>,
+        Option<Read<'a, Output>>,
+    );
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(
+            world
+                .fetch_mut::<EventChannel<GameEvent>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(&mut self, (storage, events, config, sounds, output): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("sound system is not set up");
+        let events = events.read(reader);
+        let (sounds, output) = match (sounds, output) {
+            (Some(sounds), Some(output)) => (sounds, output),
+            _ => return,
+        };
+        for event in events {
+            let handle = match event {
+                GameEvent::PiecePlaced(..) => &sounds.place,
+                GameEvent::FiveInARow(..) => &sounds.five,
+                GameEvent::Captured(..) => &sounds.capture,
+                GameEvent::InvalidClick(..) => &sounds.invalid,
+                GameEvent::GameOver => &sounds.game_over,
+                GameEvent::HintShown(..)
+                | GameEvent::FiveRemoved(..)
+                | GameEvent::Undone
+                | GameEvent::NewGame
+                | GameEvent::Restored => continue,
+            };
+            if let Some(sound) = storage.get(handle) {
+                output.play_once(sound, config.effects_volume);
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use amethyst::core::{Hidden, Transform};
+use amethyst::ecs::*;
+use amethyst::renderer::palette::Srgba;
+use amethyst::renderer::resources::Tint;
+use amethyst::renderer::{SpriteRender, Transparent};
+use log::info;
+use std::collections::HashMap;
+
+use crate::anim::{Animation, AnimationConfig, Effect};
+use crate::board::{Board, Piece, PieceRender};
+
+/// Piece entities currently rendered, indexed the same way as `Board`.
+/// Only `PieceSyncSystem` writes to it; everything else should mutate the
+/// `Board` and let the entities follow.
+#[derive(Default)]
+pub struct PieceEntities(HashMap<usize, Entity>);
+
+impl PieceEntities {
+    pub fn get(&self, idx: usize) -> Option<Entity> {
+        self.0.get(&idx).cloned()
+    }
+}
+
+/// Keeps the rendered piece entities in step with the `Board`. Entities of
+/// removed pieces fade out, are hidden and kept in a pool, then moved and
+/// re-tinted when a piece is placed again, instead of being deleted and rebuilt.
+#[derive(Default)]
+pub struct PieceSyncSystem {
+    pool: Vec<Entity>,
+    fading: Vec<Entity>,
+}
+
+impl PieceRender for PieceSyncSystem {}
+
+impl<'a> System<'a> for PieceSyncSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, SpriteRender>,
+        ReadExpect<'a, Transform>,
+        ReadExpect<'a, AnimationConfig>,
+        ReadExpect<'a, Board>,
+        Write<'a, PieceEntities>,
+        WriteStorage<'a, Piece>,
+        WriteStorage<'a, SpriteRender>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Hidden>,
+        WriteStorage<'a, Tint>,
+        WriteStorage<'a, Transparent>,
+        WriteStorage<'a, Animation>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            renderer,
+            default_trans,
+            config,
+            board,
+            mut rendered,
+            mut piece_storage,
+            mut render_storage,
+            mut transform_storage,
+            mut hiddens,
+            mut tints,
+            mut transparents,
+            mut animations,
+        ) = data;
+        let pool = &mut self.pool;
+        let fading = &mut self.fading;
+
+        fading.retain(|entity| {
+            if animations.contains(*entity) {
+                return true;
+            }
+            info!("entity {:?} released to pool", entity);
+            hiddens
+                .insert(*entity, Hidden)
+                .expect("unable to insert hidden");
+            pool.push(*entity);
+            false
+        });
+
+        rendered.0.retain(|idx, entity| {
+            let piece = board.get_piece_by_idx(*idx);
+            if piece.is_some() && piece == piece_storage.get(*entity) {
+                return true;
+            }
+            info!("entity {:?} fading out", entity);
+            hiddens.remove(*entity);
+            animations
+                .insert(*entity, Animation::new(Effect::FadeOut, config.remove))
+                .expect("unable to insert animation");
+            fading.push(*entity);
+            false
+        });
+
+        for (idx, piece) in board.pieces() {
+            if rendered.0.contains_key(&idx) {
+                continue;
+            }
+            let pos = board.idx2pos(idx);
+            let mut renderer = (*renderer).clone();
+            Self::setup_renderer(&mut renderer, piece.idx());
+            let transform = Self::setup_transform(&*default_trans, &pos);
+            let tint = Tint(Srgba::new(1.0, 1.0, 1.0, 1.0));
+            let entity = match pool.pop() {
+                Some(entity) => {
+                    piece_storage
+                        .insert(entity, piece)
+                        .expect("unable to insert piece");
+                    render_storage
+                        .insert(entity, renderer)
+                        .expect("unable to insert renderer");
+                    transform_storage
+                        .insert(entity, transform)
+                        .expect("unable to insert transform");
+                    tints.insert(entity, tint).expect("unable to insert tint");
+                    hiddens.remove(entity);
+                    entity
+                }
+                None => entities
+                    .build_entity()
+                    .with(piece, &mut piece_storage)
+                    .with(renderer, &mut render_storage)
+                    .with(transform, &mut transform_storage)
+                    .with(tint, &mut tints)
+                    .with(Transparent, &mut transparents)
+                    .build(),
+            };
+            animations
+                .insert(entity, Animation::new(Effect::Pop, config.place))
+                .expect("unable to insert animation");
+            info!("put a {:?} entity at {}", piece, pos);
+            rendered.0.insert(idx, entity);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::ai::{run_search, AiConfig, Engine};
+use crate::cursor::Coord;
+use crate::selfplay::{decode, parse_coord};
+
+/// A position with the moves that solve it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Tactic {
+    pub name: String,
+    /// In the format sent to a brain, e.g. `b+ 1 0 b0,0 w1,0`.
+    pub position: String,
+    /// Any of these moves, as `x,y`, solves the position.
+    pub best: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Suite {
+    pub tactics: Vec<Tactic>,
+}
+
+pub struct Outcome {
+    pub name: String,
+    pub played: Option<Coord>,
+    /// When the engine settled on a solving move, if it did.
+    pub solved: Option<Duration>,
+    pub time: Duration,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let played = self
+            .played
+            .map_or_else(|| "nothing".to_string(), |pos| pos.to_string());
+        match self.solved {
+            Some(solved) => write!(
+                f,
+                "pass  {} ({} after {:.2}s)",
+                self.name,
+                played,
+                solved.as_secs_f32()
+            ),
+            None => write!(
+                f,
+                "FAIL  {} (played {} in {:.2}s)",
+                self.name,
+                played,
+                self.time.as_secs_f32()
+            ),
+        }
+    }
+}
+
+impl Tactic {
+    /// Searches the position and tells whether, and how fast, it is solved.
+    pub fn run(&self, engine: &mut Engine, config: &AiConfig) -> Result<Outcome, String> {
+        let position =
+            decode(&self.position).ok_or_else(|| format!("{}: bad position", self.name))?;
+        let best = self
+            .best
+            .iter()
+            .map(|pos| parse_coord(pos).ok_or_else(|| format!("{}: bad move {}", self.name, pos)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let solves = |pos: Option<Coord>| pos.map_or(false, |pos| best.contains(&pos));
+
+        let start = Instant::now();
+        // Since the last report that did not solve it.
+        let mut solved = None;
+        let stop = Arc::new(AtomicBool::new(false));
+        let result = run_search(engine, position, config.clone(), stop, |result| {
+            match (solves(result.best), solved) {
+                (true, None) => solved = Some(start.elapsed()),
+                (false, _) => solved = None,
+                _ => {}
+            }
+        });
+        let time = start.elapsed();
+        if !solves(result.best) {
+            solved = None;
+        } else if solved.is_none() {
+            solved = Some(time);
+        }
+        Ok(Outcome {
+            name: self.name.clone(),
+            played: result.best,
+            solved,
+            time,
+        })
+    }
+}